@@ -1,6 +1,9 @@
 use std::fs::File;
 use std::thread;
+use std::time::Duration;
 use anyhow::Result;
+use chrono::{DateTime, Local};
+use clap::{Args, Parser, Subcommand};
 use crossbeam_channel::unbounded;
 use crossbeam_channel;
 use log::*;
@@ -8,34 +11,295 @@ use simplelog::*;
 use ropencv::broadcast::Broadcast;
 
 use ropencv::signals::*;
-use ropencv::cam;
+use ropencv::cam::{self, RunOptions};
+use ropencv::config::{Config, DiffConfig, ScheduleWindowConfig, TelegramConfig};
+use ropencv::notify::{self, Notifier};
+use ropencv::reload;
+use ropencv::replay::replay;
+use ropencv::retention::{self, clip_extension, list_clips};
+use ropencv::schedule;
+#[cfg(feature="telegram")]
 use ropencv::telegram;
 
-fn main() {
-    init_logger("log.log").unwrap();
+#[derive(Parser)]
+#[command(about = "Motion detection for IP and USB cameras", args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    // Without a subcommand the cameras are watched, so `main --headless` keeps working
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+
+#[derive(Args)]
+struct RunArgs {
+    #[arg(long, default_value = "config.toml")]
+    config: String,
+    #[arg(long, default_value = "log.log")]
+    log: String,
+    /// Don't show preview windows
+    #[arg(long)]
+    headless: bool,
+}
+
+
+#[derive(Subcommand)]
+enum Command {
+    /// Watch the cameras (the default)
+    Run(RunArgs),
+    /// Validate the config and print the effective settings
+    CheckConfig {
+        #[arg(long, default_value = "config.toml")]
+        config: String,
+    },
+    /// Run motion detection on a video file and print the motion found
+    Replay {
+        video: String,
+        #[arg(long, default_value = "config.toml")]
+        config: String,
+        /// Camera whose settings to use; the first one by default
+        #[arg(long)]
+        camera: Option<String>,
+    },
+    /// List the recorded clips of every camera
+    ListClips {
+        #[arg(long, default_value = "config.toml")]
+        config: String,
+    },
+}
+
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command.unwrap_or(Command::Run(cli.run)) {
+        Command::Run(RunArgs { config, log, headless }) => run(config, &log, headless),
+        Command::CheckConfig { config } => check_config(&config),
+        Command::Replay { video, config, camera } => replay_video(&video, &config, camera),
+        Command::ListClips { config } => print_clips(&config),
+    }
+}
+
+
+fn check_config(config_path: &str) -> Result<()> {
+    let config = Config::load(config_path)?;
+
+    let validation = config.validate();
+    for problem in &validation.problems {
+        println!("{}", problem);
+    }
+    if validation.is_fatal() {
+        return Err(anyhow::Error::msg(format!("{} is invalid", config_path)))
+    }
+    print!("{}", config);
+    println!();
+    println!("{} is valid", config_path);
+    Ok(())
+}
+
+
+fn replay_video(video: &str, config_path: &str, camera: Option<String>) -> Result<()> {
+    let config = Config::load(config_path)?;
+    config.validate().into_result()?;
+    let camera_config = match &camera {
+        Some(name) => config.cameras.iter().find(|c| &c.name == name),
+        None => config.cameras.first(),
+    }.ok_or_else(|| anyhow::Error::msg(format!("No camera {} in {}", camera.unwrap_or_default(), config_path)))?;
+
+    let events = replay(camera_config, video)?;
+    for event in &events {
+        print!("{} - {}  peak {:.1}%", format_offset(event.start), format_offset(event.end), event.peak_ratio * 100.);
+        if !event.zones.is_empty() {
+            print!("  zones: {}", event.zones.join(", "));
+        }
+        println!();
+    }
+    println!("{} motion events", events.len());
+    Ok(())
+}
+
+
+fn format_offset(offset: Duration) -> String {
+    let millis = offset.as_millis();
+    format!("{:02}:{:02}:{:02}.{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+
+fn print_clips(config_path: &str) -> Result<()> {
+    let config = Config::load(config_path)?;
+    for camera in &config.cameras {
+        println!("{} ({})", camera.name, camera.output.result_folder);
+        let extension = clip_extension(&camera.output.result_filename_format);
+        let mut clips = match list_clips(&camera.output.result_folder, &extension) {
+            Ok(clips) => clips,
+            Err(e) => {
+                println!("  cannot list clips: {}", e);
+                continue
+            }
+        };
+        clips.sort_by_key(|c| c.modified);
+        for clip in clips {
+            let modified: DateTime<Local> = clip.modified.into();
+            println!(
+                "  {}  {:>8.1} MB  {}",
+                modified.format("%Y-%m-%d %H:%M:%S"),
+                clip.size as f64 / (1024. * 1024.),
+                clip.path.display(),
+            );
+        }
+    }
+    Ok(())
+}
+
+
+fn run(config_path: String, log: &str, headless: bool) -> Result<()> {
+    init_logger(log)?;
+
+    let config = Config::load(&config_path)?;
+    config.validate().into_result()?;
+    let options = RunOptions {
+        headless: config.headless || headless,
+        arming: config.arming.clone(),
+    };
 
     let (sender, receiver) = unbounded();
     let mut broadcast = Broadcast::new(receiver);
 
-    let camera_thread = run_camera(sender.clone(), broadcast.subscribe());
+    handle_termination(sender.clone());
+
+    // Started first, so that bad credentials stop us before anything else runs
+    let camera_names: Vec<String> = config.cameras.iter().map(|camera| camera.name.clone()).collect();
+    let telegram_thread = match config.telegram.enabled {
+        true => run_telegram(config.telegram.clone(), camera_names, sender.clone(), &mut broadcast)?,
+        false => None,
+    };
+
+    let retention_thread = run_retention(config.cameras.clone(), sender.clone(), broadcast.subscribe());
+
+    let schedule_thread = match config.arming.schedule.is_empty() {
+        true => None,
+        false => Some(run_schedule(config.arming.schedule.clone(), sender.clone(), broadcast.subscribe())),
+    };
+
+    let reload_thread = run_reload(config_path, config.clone(), sender.clone(), broadcast.subscribe());
 
-    let telegram_thread = run_telegram(sender.clone(), broadcast.subscribe());
+    let camera_threads: Vec<_> = config.cameras.into_iter()
+        .map(|camera| run_camera(camera, options.clone(), sender.clone(), broadcast.subscribe()))
+        .collect();
+
+    let notifiers = notify::notifiers(&config.notify)?;
+    let notify_thread = match notifiers.is_empty() {
+        true => None,
+        false => Some(run_notify(notifiers, broadcast.subscribe())),
+    };
 
     thread::spawn(move || broadcast.run_loop());
 
-    camera_thread.join().expect("Camera thread has panicked").unwrap();
+    let mut failed = 0;
+    for camera_thread in camera_threads {
+        let name = camera_thread.thread().name().unwrap_or("camera").to_string();
+        failed += !join(&name, camera_thread) as usize;
+    }
+
+    // Cameras also stop when their source is exhausted, so the other threads may not have been told yet
+    sender.send(Signal::Shutdown)?;
+    sender.send(Signal::CamerasStopped)?;
+
+    let mut threads = vec![("retention", retention_thread), ("reload", reload_thread)];
+    threads.extend(schedule_thread.map(|thread| ("schedule", thread)));
+    threads.extend(notify_thread.map(|thread| ("notify", thread)));
+    threads.extend(telegram_thread.map(|thread| ("telegram", thread)));
+    for (name, thread) in threads {
+        failed += !join(name, thread) as usize;
+    }
+
+    if failed > 0 {
+        return Err(anyhow::Error::msg(format!("{} threads have failed", failed)))
+    }
+    info!("Shut down");
+    Ok(())
+}
+
 
-    telegram_thread.join().expect("Telegram thread has panicked").unwrap();
+/// Waits for a thread to end; false if it has failed
+fn join(name: &str, thread: thread::JoinHandle<Result<()>>) -> bool {
+    match thread.join() {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            error!("{} thread has failed: {}", name, e);
+            false
+        }
+        Err(_) => {
+            error!("{} thread has panicked", name);
+            false
+        }
+    }
 }
 
 
-fn run_camera(sender: Sender, receiver: Receiver) -> thread::JoinHandle<Result<()>> {
-    thread::spawn(|| { cam::run(sender, receiver) })
+fn run_camera(config: DiffConfig, options: RunOptions, sender: Sender, receiver: Receiver) -> thread::JoinHandle<Result<()>> {
+    info!("Starting camera {}", config.name);
+    thread::Builder::new()
+        .name(config.name.clone())
+        .spawn(move || { cam::run(config, options, sender, receiver) })
+        .expect("Cannot spawn camera thread")
 }
 
-fn run_telegram(sender: Sender, receiver: Receiver) -> thread::JoinHandle<Result<()>> {
+fn run_retention(cameras: Vec<DiffConfig>, sender: Sender, receiver: Receiver) -> thread::JoinHandle<Result<()>> {
+    info!("Starting retention manager");
+    thread::spawn(|| { retention::run(cameras, sender, receiver) })
+}
+
+fn run_schedule(schedule: Vec<ScheduleWindowConfig>, sender: Sender, receiver: Receiver) -> thread::JoinHandle<Result<()>> {
+    info!("Starting arming schedule");
+    thread::spawn(|| { schedule::run(schedule, sender, receiver) })
+}
+
+fn run_reload(path: String, running: Config, sender: Sender, receiver: Receiver) -> thread::JoinHandle<Result<()>> {
+    info!("Watching {} for changes", path);
+    thread::spawn(|| { reload::run(path, running, sender, receiver) })
+}
+
+/// Turns SIGINT/SIGTERM into a Shutdown signal
+fn handle_termination(sender: Sender) {
+    ctrlc::set_handler(move || {
+        info!("Termination requested");
+        sender.send(Signal::Shutdown).ok();
+    }).expect("Cannot set termination handler");
+}
+
+fn run_notify(notifiers: Vec<Box<dyn Notifier>>, receiver: Receiver) -> thread::JoinHandle<Result<()>> {
+    info!("Starting {} notifiers", notifiers.len());
+    thread::spawn(|| { notify::run(notifiers, receiver) })
+}
+
+#[cfg(feature="telegram")]
+fn run_telegram(
+    config: TelegramConfig,
+    cameras: Vec<String>,
+    sender: Sender,
+    broadcast: &mut Broadcast<Signal>) -> Result<Option<thread::JoinHandle<Result<()>>>>
+{
+    if !telegram::has_credentials(&config)? {
+        warn!("TELOXIDE_TOKEN or chat ids are not set, running without the telegram bot");
+        return Ok(None)
+    }
     info!("Starting telegram bot");
-    thread::spawn(|| { telegram::run(sender, receiver) })
+    let receiver = broadcast.subscribe();
+    Ok(Some(thread::spawn(|| { telegram::run(config, cameras, sender, receiver) })))
+}
+
+#[cfg(not(feature="telegram"))]
+fn run_telegram(
+    _config: TelegramConfig,
+    _cameras: Vec<String>,
+    _sender: Sender,
+    _broadcast: &mut Broadcast<Signal>) -> Result<Option<thread::JoinHandle<Result<()>>>>
+{
+    info!("Built without the telegram feature, running without the telegram bot");
+    Ok(None)
 }
 
 
@@ -45,13 +309,13 @@ fn init_logger(filename: &str) -> Result<()> {
         vec![
             TermLogger::new(
                 LevelFilter::Debug,
-                Config::default(),
+                simplelog::Config::default(),
                 TerminalMode::Mixed,
                 ColorChoice::Auto
             ),
             WriteLogger::new(
                 LevelFilter::Info,
-                Config::default(),
+                simplelog::Config::default(),
                 File::create(filename)?
             )
         ]
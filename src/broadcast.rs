@@ -18,23 +18,21 @@ impl<T> Broadcast<T> where T: Clone+Send+Sync+'static {
         receiver
     }
 
-    pub fn run_loop(&self) -> anyhow::Result<()> {
+    pub fn run_loop(&mut self) -> anyhow::Result<()> {
         loop {
             self.recv()?;
         }
     }
 
-    pub fn recv(&self) -> anyhow::Result<()> {
-        println!("RECEIVING");
+    pub fn recv(&mut self) -> anyhow::Result<()> {
         let msg = self.receiver.recv()?;
         self.send(msg)?;
         Ok(())
     }
 
-    pub fn send(&self, msg: T) -> anyhow::Result<()> {
-        for sender in &self.subscribers {
-            sender.send(msg.clone())?;
-        }
+    /// Sends to every subscriber; subscribers that have gone away are dropped
+    pub fn send(&mut self, msg: T) -> anyhow::Result<()> {
+        self.subscribers.retain(|sender| sender.send(msg.clone()).is_ok());
         Ok(())
     }
 }
@@ -1,33 +1,82 @@
-use std::fs;
-use std::time::Duration;
-use toml;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 use anyhow::Result;
-use log::info;
+use log::{info, warn};
 use opencv::{
-    videoio::{VideoCapture, VideoCaptureTrait, CAP_PROP_FRAME_WIDTH, CAP_PROP_FRAME_HEIGHT, CAP_ANY},
     highgui::{imshow, wait_key},
     prelude::Mat,
 };
 use opencv::core::{BORDER_DEFAULT, BorderTypes, Point, Scalar, Size};
-use opencv::imgproc::{InterpolationFlags, MORPH_ELLIPSE, THRESH_BINARY};
+use opencv::imgproc::{InterpolationFlags, FONT_HERSHEY_SIMPLEX, LINE_AA, MORPH_ELLIPSE, THRESH_BINARY};
 use simplelog::Config;
 
-use crate::camera::{Handler, MatDiff, MotionDetect, StatesConfig, Writer};
+use crate::camera::{
+    Association, BackgroundSubtractorDetector, DnnClassifier, DnnInput, Handler, MatDiff, MotionDetect, MotionDetector,
+    Overlay, PeopleDetector, RunningAverageDetector, StatesConfig, Throttled, Tracker, Writer, Zones,
+};
 use crate::cv::*;
-use crate::config::DiffConfig;
+use crate::config::{ArmingConfig, DetectorConfig, DiffConfig, OutputFileConfig, TrackingMethod};
+use crate::retention::{clip_extension, folder_size};
+use crate::supervisor::{SourceRead, SupervisedSource};
 
 
 use crate::signals::*;
 
 
-pub fn run(sender: Sender, receiver: Receiver) -> Result<()> {
-    let camera = prepare_camera()?;
-    let motiondetect = configure(sender)?;
+/// Settings shared by all camera threads
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    // No preview window; frames are paced by the source framerate
+    pub headless: bool,
+    pub arming: ArmingConfig,
+}
+
 
-    let mut runner = CameraRunner::new(camera, motiondetect);
+/// Runs the camera loop until the source is exhausted or a shutdown is requested
+///
+/// In headless mode no preview window is shown and frames are paced by the source framerate;
+/// otherwise pressing `q` in the preview window requests a shutdown of all threads.
+pub fn run(mut config: DiffConfig, options: RunOptions, sender: Sender, receiver: Receiver) -> Result<()> {
+    let headless = options.headless;
+    let source = SupervisedSource::open(&config.name, &config.source, &config.reconnect, sender.clone())?;
+    let motiondetect = configure(&config, sender.clone())?;
+
+    let mut pacer = Pacer::new(source.fps());
+    let mut runner = CameraRunner::new(&config.name, source, motiondetect, headless)
+        .with_status(sender.clone(), &config.output)
+        .with_arming(options.arming);
+    if config.output.overlay.enabled && config.output.overlay.on_snapshots {
+        runner = runner.with_snapshot_overlay(configure_overlay(&config));
+    }
 
     loop {
         match receiver.try_recv() {
+            Ok(Signal::Shutdown) => {
+                info!("[{}] Shutting down", config.name);
+                runner.flush()?;
+                break;
+            }
+            Ok(Signal::ReloadConfig(configs)) => {
+                // The source stays as opened; the reload thread reports changes to it
+                let new_config = configs.into_iter()
+                    .find(|c| c.name == config.name)
+                    .map(|c| DiffConfig { source: config.source.clone(), reconnect: config.reconnect.clone(), ..c })
+                    .filter(|c| *c != config);
+                // Unchanged cameras keep their detector history and tracks
+                if let Some(new_config) = new_config {
+                    match runner.reconfigure(&new_config, sender.clone()) {
+                        Ok(()) => {
+                            info!("[{}] Configuration reloaded", config.name);
+                            config = new_config;
+                            sender.send(Signal::ConfigReloaded(config.name.clone()))?;
+                        }
+                        Err(e) => {
+                            warn!("[{}] Cannot apply reloaded configuration: {}", config.name, e);
+                            sender.send(Signal::ConfigError(format!("{}: {}", config.name, e)))?;
+                        }
+                    }
+                }
+            }
             Ok(signal) => {
                 runner.handle_signal(signal)?;
             }
@@ -35,9 +84,21 @@ pub fn run(sender: Sender, receiver: Receiver) -> Result<()> {
         };
 
         runner = runner.next()?;
+        if runner.is_finished() {
+            info!("[{}] Frame source exhausted", config.name);
+            break;
+        }
+
+        if headless {
+            pacer.wait(runner.is_running());
+            continue;
+        }
 
         let key = wait_key(1)?;
         if key == 113 {
+            // The other cameras stop on the broadcast Shutdown; this one stops right away
+            sender.send(Signal::Shutdown)?;
+            runner.flush()?;
             break;
         }
 
@@ -47,63 +108,259 @@ pub fn run(sender: Sender, receiver: Receiver) -> Result<()> {
 }
 
 
+/// Keeps the loop from running faster than the source framerate
+struct Pacer {
+    interval: Option<Duration>,
+    last_frame: Instant,
+}
+
+
+impl Pacer {
+    // How long to sleep between signal checks while the camera is stopped
+    const IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
+    fn new(fps: Option<f64>) -> Self {
+        Self {
+            interval: fps.map(|fps| Duration::from_secs_f64(1. / fps)),
+            last_frame: Instant::now(),
+        }
+    }
+
+    fn wait(&mut self, running: bool) {
+        let interval = match (running, self.interval) {
+            (false, _) => Self::IDLE_INTERVAL,
+            (true, Some(interval)) => interval,
+            // Live sources block on read until the next frame arrives
+            (true, None) => return,
+        };
+        if let Some(remaining) = interval.checked_sub(self.last_frame.elapsed()) {
+            sleep(remaining);
+        }
+        self.last_frame = Instant::now();
+    }
+}
+
+
 pub struct  CameraRunner {
-    camera: VideoCapture,
+    name: String,
+    source: SupervisedSource,
     motiondetect: MotionDetect,
 
     camera_running: bool,
+    armed: bool,
+    finished: bool,
+    headless: bool,
+    frames_processed: u64,
+    last_frame: Option<Mat>,
+    arming: ArmingConfig,
+
+    // Where status reports and snapshots go, and the output whose clips are measured
+    status: Option<(Sender, OutputFileConfig)>,
+    snapshot_overlay: Option<Overlay>,
 }
 
 
 impl CameraRunner {
-    pub fn new(camera: VideoCapture, motiondetect: MotionDetect) -> Self {
+    pub fn new(
+        name: &str,
+        source: SupervisedSource,
+        motiondetect: MotionDetect,
+        headless: bool) -> Self
+    {
         Self {
-            camera,
+            name: name.to_string(),
+            source,
             motiondetect,
-            camera_running: true
+            camera_running: true,
+            armed: true,
+            finished: false,
+            headless,
+            frames_processed: 0,
+            last_frame: None,
+            arming: ArmingConfig::default(),
+            status: None,
+            snapshot_overlay: None,
+        }
+    }
+
+    /// Enables answering StatusRequest and SnapshotRequest; the clips of the output are measured for disk usage
+    pub fn with_status(self, sender: Sender, output: &OutputFileConfig) -> Self {
+        Self {
+            status: Some((sender, output.clone())),
+            ..self
+        }
+    }
+
+    pub fn with_arming(mut self, arming: ArmingConfig) -> Self {
+        let armed = arming.armed_on_start;
+        self.arming = arming;
+        self.set_armed(armed);
+        self
+    }
+
+    pub fn with_snapshot_overlay(self, overlay: Overlay) -> Self {
+        Self {
+            snapshot_overlay: Some(overlay),
+            ..self
         }
     }
 
+    pub fn is_running(&self) -> bool {
+        self.camera_running
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
     pub fn next(mut self) -> Result<Self> {
-        if !self.camera_running { return Ok(self) }
+        if !self.camera_running || self.finished { return Ok(self) }
 
-        let frame = self.read_camera()?;
+        let frame = match self.source.read()? {
+            SourceRead::Frame(frame) => frame,
+            SourceRead::Lost => {
+                self.motiondetect.flush()?;
+                return Ok(self)
+            }
+            SourceRead::Waiting => return Ok(self),
+            SourceRead::Exhausted => {
+                self.motiondetect.flush()?;
+                self.finished = true;
+                return Ok(self)
+            }
+        };
         self.motiondetect = self.motiondetect.new_frame(&frame)?;
+        self.frames_processed += 1;
 
         self.show_frame(&frame)?;
+        self.last_frame = Some(frame);
 
         Ok(self)
     }
 
+    /// Finishes the clip in progress
+    pub fn flush(&mut self) -> Result<()> {
+        self.motiondetect.flush()
+    }
+
     fn show_frame(&self, frame: &Mat) -> Result<()> {
-        Ok(imshow("Camera", frame)?)
+        if self.headless { return Ok(()) }
+        Ok(imshow(&self.name, frame)?)
+    }
+
+    /// Applies a changed camera config between frames, keeping a clip in progress
+    ///
+    /// The frame source is not reopened; changing it needs a restart.
+    pub fn reconfigure(&mut self, config: &DiffConfig, sender: Sender) -> Result<()> {
+        self.motiondetect.reconfigure(configure(config, sender)?);
+        // The new writer starts with notifications on
+        self.set_armed(self.armed);
+
+        if let Some((_, output)) = &mut self.status {
+            *output = config.output.clone();
+        }
+        self.snapshot_overlay = match config.output.overlay.enabled && config.output.overlay.on_snapshots {
+            true => Some(configure_overlay(config)),
+            false => None,
+        };
+        Ok(())
     }
 
     fn handle_signal(&mut self, signal: Signal) -> Result<()> {
         match signal {
-            Signal::StopCamera => {
-                info!("Stopping Camera");
+            Signal::StopCamera(camera) if self.is_addressed(&camera) => {
+                info!("[{}] Stopping Camera", self.name);
                 self.camera_running = false;
             },
-            Signal::StartCamera => {
-                info!("(Re)starting Camera");
+            Signal::StartCamera(camera) if self.is_addressed(&camera) => {
+                info!("[{}] (Re)starting Camera", self.name);
                 self.camera_running = true;
             },
+            Signal::Arm(camera) if self.is_addressed(&camera) => {
+                info!("[{}] Arming", self.name);
+                self.set_armed(true);
+            },
+            Signal::Disarm(camera) if self.is_addressed(&camera) => {
+                info!("[{}] Disarming", self.name);
+                self.set_armed(false);
+            },
+            Signal::StatusRequest => {
+                self.report_status()?;
+            },
+            Signal::SnapshotRequest(camera) if self.is_addressed(&camera) => {
+                self.send_snapshot()?;
+            },
             _ => {}
         }
         Ok(())
     }
 
-    fn read_camera(&mut self) -> Result<Mat> {
-        read_camera(&mut self.camera)
+    fn set_armed(&mut self, armed: bool) {
+        self.armed = armed;
+        self.motiondetect.set_recording_enabled(armed || !self.arming.suppress_recordings);
+        self.motiondetect.set_notify(armed || !self.arming.suppress_notifications);
+    }
+
+    fn report_status(&self) -> Result<()> {
+        let (sender, output) = match &self.status {
+            Some(status) => status,
+            None => return Ok(()),
+        };
+        let extension = clip_extension(&output.result_filename_format);
+        let disk_usage = folder_size(&output.result_folder, &extension).unwrap_or_else(|e| {
+            warn!("[{}] Cannot measure {}: {}", self.name, output.result_folder, e);
+            0
+        });
+        sender.send(Signal::StatusReport(CameraStatus {
+            camera: self.name.clone(),
+            running: self.camera_running,
+            armed: self.armed,
+            state: self.motiondetect.state_name().to_string(),
+            frames_processed: self.frames_processed,
+            last_motion: self.motiondetect.last_motion(),
+            disk_usage,
+        }))?;
+        Ok(())
+    }
+
+    fn send_snapshot(&self) -> Result<()> {
+        let sender = match &self.status {
+            Some((sender, _)) => sender,
+            None => return Ok(()),
+        };
+        let frame = match &self.last_frame {
+            Some(frame) => frame,
+            None => {
+                info!("[{}] No frame captured yet, cannot take a snapshot", self.name);
+                sender.send(Signal::SnapshotUnavailable(self.name.clone()))?;
+                return Ok(())
+            }
+        };
+
+        let jpeg = match &self.snapshot_overlay {
+            Some(overlay) => encode_jpeg(&overlay.apply(frame, self.motiondetect.last_result())?, 90)?,
+            None => encode_jpeg(frame, 90)?,
+        };
+
+        sender.send(Signal::Snapshot(Snapshot {
+            camera: self.name.clone(),
+            jpeg,
+        }))?;
+        Ok(())
+    }
+
+    fn is_addressed(&self, camera: &Option<String>) -> bool {
+        match camera {
+            Some(name) => name == &self.name,
+            None => true,
+        }
     }
 
 }
 
 
-fn configure(sender: Sender) -> Result<MotionDetect> {
-    let config_toml = fs::read_to_string("config.toml")?;
-    let config: DiffConfig = toml::from_str(&config_toml)?;
+/// Builds the motion detector described by the camera config
+pub fn configure_detector(config: &DiffConfig) -> Result<Box<dyn MotionDetector>> {
     let diff = MatDiff::new(
         GaussianBlur::new(
             Size::new(config.blur_radius, config.blur_radius),
@@ -132,7 +389,26 @@ fn configure(sender: Sender) -> Result<MotionDetect> {
         ),
         FindContours::default(),
         config.sensitivity
+    ).with_zones(
+        Zones::new(config.zones.iter().map(|zone| zone.into()).collect())
     );
+    let detector: Box<dyn MotionDetector> = match config.detector {
+        DetectorConfig::Diff => Box::new(diff),
+        DetectorConfig::Mog2 { history, var_threshold, detect_shadows, learning_rate } => {
+            Box::new(BackgroundSubtractorDetector::mog2(
+                diff, history, var_threshold, detect_shadows, learning_rate)?)
+        }
+        DetectorConfig::Knn { history, dist2_threshold, detect_shadows, learning_rate } => {
+            Box::new(BackgroundSubtractorDetector::knn(
+                diff, history, dist2_threshold, detect_shadows, learning_rate)?)
+        }
+        DetectorConfig::Average { alpha } => Box::new(RunningAverageDetector::new(diff, alpha)),
+    };
+    Ok(detector)
+}
+
+
+fn configure(config: &DiffConfig, sender: Sender) -> Result<MotionDetect> {
     let writer = Writer::new(
         VideoFileDirWriter::new(
             VideoFileWriter::new(
@@ -145,32 +421,107 @@ fn configure(sender: Sender) -> Result<MotionDetect> {
             &config.output.result_filename_format,
             &config.output.result_folder,
         ),
-        sender
+        sender,
+        &config.name,
     );
-    let md = MotionDetect::new(
-        diff,
+    let mut required_labels = Vec::new();
+    if config.people.enabled && config.people.require {
+        required_labels.push("person".to_string());
+    }
+    if config.classifier.enabled && config.classifier.require {
+        required_labels.extend(config.classifier.include.iter().cloned());
+    }
+    let writer = writer.with_required_labels(required_labels);
+    let mut md = MotionDetect::new(
+        configure_detector(config)?,
         StatesConfig {
             writer: writer,
             min_video_duration: Duration::from_secs(config.min_video_duration),
             max_video_duration: Duration::from_secs(config.max_video_duration),
             max_idle_gap: Duration::from_secs(config.max_idle_gap),
+            pre_roll: Duration::from_secs(config.pre_roll_duration),
+            post_roll: Duration::from_secs(config.post_roll_duration),
         }
     );
 
+    if config.people.enabled {
+        // One person is enough to label the clip
+        let people = PeopleDetector::new(config.people.min_confidence, config.people.padding)?;
+        md = md.with_classifier(Box::new(
+            Throttled::new(people, config.people.every_n_frames).with_until_found(true)
+        ));
+    }
+
+    if config.classifier.enabled {
+        md = md.with_classifier(Box::new(
+            Throttled::new(configure_classifier(config)?, config.classifier.every_n_frames)
+        ));
+    }
+
+    if config.tracking.enabled {
+        let association = match config.tracking.method {
+            TrackingMethod::Iou => Association::Iou(config.tracking.min_iou),
+            TrackingMethod::Centroid => Association::Centroid(config.tracking.max_distance),
+        };
+        md = md.with_tracker(
+            Tracker::new(association, config.tracking.max_missed).with_kalman(config.tracking.kalman)
+        );
+    }
+
+    if config.output.overlay.enabled {
+        md = md.with_overlay(configure_overlay(config));
+    }
+
     Ok(md)
 }
 
 
-fn prepare_camera() -> Result<VideoCapture> {
-    let mut cam = VideoCapture::new(0, CAP_ANY)?;
-    cam.set(CAP_PROP_FRAME_WIDTH, 640_f64)?;
-    cam.set(CAP_PROP_FRAME_HEIGHT, 480_f64)?;
-    Ok(cam)
+fn configure_classifier(config: &DiffConfig) -> Result<DnnClassifier> {
+    let classifier_config = &config.classifier;
+    let [mean_1, mean_2, mean_3] = classifier_config.mean;
+    let classifier = DnnClassifier::new(
+        &classifier_config.model,
+        &classifier_config.config,
+        &classifier_config.labels,
+        DnnInput {
+            size: Size::new(classifier_config.input_width, classifier_config.input_height),
+            scale: classifier_config.scale,
+            mean: Scalar::new(mean_1, mean_2, mean_3, 0.),
+            swap_rb: classifier_config.swap_rb,
+        },
+    )?
+        .with_softmax(classifier_config.softmax)
+        .with_min_confidence(classifier_config.min_confidence)
+        .with_padding(classifier_config.padding)
+        .with_classes(classifier_config.include.clone(), classifier_config.exclude.clone());
+    Ok(classifier)
 }
 
 
-fn read_camera(camera: &mut VideoCapture) -> Result<Mat> {
-    let mut result = Mat::default();
-    camera.read(&mut result)?;
-    Ok(result)
+fn configure_overlay(config: &DiffConfig) -> Overlay {
+    let overlay_config = &config.output.overlay;
+    let mut overlay = Overlay::new(
+        &config.name,
+        &overlay_config.timestamp_format,
+        PutText::new(
+            FONT_HERSHEY_SIMPLEX,
+            overlay_config.font_scale,
+            overlay_config.color,
+            overlay_config.thickness,
+            LINE_AA,
+        ),
+        overlay_config.position,
+    );
+    if overlay_config.draw_boxes {
+        overlay = overlay.with_boxes(
+            DrawRectangles::new(overlay_config.motion_color, overlay_config.thickness, LINE_AA)
+        );
+    }
+    if overlay_config.draw_contours {
+        overlay = overlay.with_contours(
+            DrawContours::new(overlay_config.motion_color, overlay_config.thickness, LINE_AA)
+                .with_index(-1)
+        );
+    }
+    overlay
 }
@@ -0,0 +1,216 @@
+use anyhow::Result;
+use opencv::prelude::*;
+use opencv::core::{Rect, Size, Vector};
+use opencv::imgproc::{resize, INTER_LINEAR};
+use opencv::objdetect::HOGDescriptor;
+use super::result::MotionResult;
+
+
+/// An object recognised in a frame
+#[derive(Clone, Debug)]
+pub struct Detection {
+    pub label: String,
+    pub confidence: f64,
+    // Where in the frame, in pixels
+    pub region: Rect,
+}
+
+
+/// Tells what caused the motion, looking at the motion regions of a frame
+pub trait Classifier {
+    fn classify(&mut self, frame: &Mat, motion: &MotionResult) -> Result<Vec<Detection>>;
+
+    /// Called when a motion event is over
+    fn reset(&mut self) {}
+}
+
+
+/// Runs a classifier on every n-th frame of a motion event only
+///
+/// Classifiers are slow on the CPU and run on the camera thread, so skipping frames keeps the
+/// capture rate up on the frames being recorded.
+pub struct Throttled<C: Classifier> {
+    classifier: C,
+    every: u32,
+    // Motion frames seen in the current event
+    frames: u32,
+    // Rest for the remainder of the event once something was found
+    until_found: bool,
+    found: bool,
+}
+
+
+impl<C: Classifier> Throttled<C> {
+    pub fn new(classifier: C, every: u32) -> Self {
+        Self {
+            classifier,
+            every: every.max(1),
+            frames: 0,
+            until_found: false,
+            found: false,
+        }
+    }
+
+    pub fn with_until_found(self, until_found: bool) -> Self {
+        Self { until_found, ..self }
+    }
+}
+
+
+impl<C: Classifier> Classifier for Throttled<C> {
+    fn classify(&mut self, frame: &Mat, motion: &MotionResult) -> Result<Vec<Detection>> {
+        let skip = self.found || self.frames % self.every != 0;
+        self.frames += 1;
+        if skip {
+            return Ok(Vec::new())
+        }
+
+        let detections = self.classifier.classify(frame, motion)?;
+        self.found = self.until_found && !detections.is_empty();
+        Ok(detections)
+    }
+
+    fn reset(&mut self) {
+        self.frames = 0;
+        self.found = false;
+        self.classifier.reset();
+    }
+}
+
+
+/// Finds people with OpenCV's HOG descriptor and its default people detector
+///
+/// Confidence is the SVM score of the detection; around 0.5 and above is usually a person.
+pub struct PeopleDetector {
+    hog: HOGDescriptor,
+    min_confidence: f64,
+    // Added around motion regions, as motion often covers only part of a person
+    padding: i32,
+}
+
+
+impl PeopleDetector {
+    pub fn new(min_confidence: f64, padding: i32) -> Result<Self> {
+        let mut hog = HOGDescriptor::default()?;
+        hog.set_svm_detector(&HOGDescriptor::get_default_people_detector()?)?;
+        Ok(Self { hog, min_confidence, padding })
+    }
+
+    fn detect_in(&self, frame: &Mat, region: Rect) -> Result<Vec<Detection>> {
+        let crop = Mat::roi(frame, region)?;
+
+        // The detection window is 64x128; smaller crops are scaled up to fit it
+        let window = self.hog.win_size();
+        let scale = f64::max(
+            1.,
+            f64::max(window.width as f64 / region.width as f64, window.height as f64 / region.height as f64),
+        );
+        let mut input = crop;
+        if scale > 1. {
+            let mut scaled = Mat::default();
+            resize(&input, &mut scaled, Size::default(), scale, scale, INTER_LINEAR)?;
+            input = scaled;
+        }
+
+        let mut found = Vector::<Rect>::new();
+        let mut weights = Vector::<f64>::new();
+        self.hog.detect_multi_scale_weights(
+            &input, &mut found, &mut weights,
+            0., Size::new(8, 8), Size::new(8, 8), 1.05, 2., false,
+        )?;
+
+        let detections = found.iter().zip(weights.iter())
+            .filter(|(_, weight)| *weight >= self.min_confidence)
+            .map(|(rect, weight)| Detection {
+                label: "person".to_string(),
+                confidence: weight,
+                region: Rect::new(
+                    region.x + (rect.x as f64 / scale) as i32,
+                    region.y + (rect.y as f64 / scale) as i32,
+                    (rect.width as f64 / scale) as i32,
+                    (rect.height as f64 / scale) as i32,
+                ),
+            })
+            .collect();
+        Ok(detections)
+    }
+}
+
+
+impl Classifier for PeopleDetector {
+    fn classify(&mut self, frame: &Mat, motion: &MotionResult) -> Result<Vec<Detection>> {
+        let frame_size = frame.size()?;
+        let mut detections = Vec::new();
+        for region in &motion.regions {
+            let padded = pad(*region, self.padding, frame_size);
+            if padded.width == 0 || padded.height == 0 { continue }
+            detections.extend(self.detect_in(frame, padded)?);
+        }
+        Ok(detections)
+    }
+}
+
+
+/// Grows a rectangle by `padding` on every side, keeping it within the frame
+pub fn pad(region: Rect, padding: i32, frame_size: Size) -> Rect {
+    let x = (region.x - padding).max(0);
+    let y = (region.y - padding).max(0);
+    let right = (region.x + region.width + padding).min(frame_size.width);
+    let bottom = (region.y + region.height + padding).min(frame_size.height);
+    Rect::new(x, y, (right - x).max(0), (bottom - y).max(0))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use opencv::prelude::Mat;
+    use opencv::core::{Rect, Size};
+    use crate::camera::MotionResult;
+    use super::{pad, Classifier, Detection, Throttled};
+
+
+    /// Finds a person on every frame it is asked about
+    struct Counting {
+        calls: usize,
+    }
+
+
+    impl Classifier for Counting {
+        fn classify(&mut self, _frame: &Mat, _motion: &MotionResult) -> Result<Vec<Detection>> {
+            self.calls += 1;
+            Ok(vec![Detection { label: "person".to_string(), confidence: 1., region: Rect::default() }])
+        }
+    }
+
+
+    #[test]
+    fn padding_stays_within_frame() {
+        let frame = Size::new(640, 480);
+        assert_eq!(pad(Rect::new(100, 100, 50, 50), 10, frame), Rect::new(90, 90, 70, 70));
+        assert_eq!(pad(Rect::new(5, 470, 50, 10), 10, frame), Rect::new(0, 460, 65, 20));
+    }
+
+    #[test]
+    fn throttled_runs_every_nth_frame() {
+        let mut throttled = Throttled::new(Counting { calls: 0 }, 3);
+        let found: Vec<bool> = (0..7)
+            .map(|_| !throttled.classify(&Mat::default(), &MotionResult::none()).unwrap().is_empty())
+            .collect();
+        assert_eq!(found, vec![true, false, false, true, false, false, true]);
+        assert_eq!(throttled.classifier.calls, 3);
+    }
+
+    #[test]
+    fn throttled_until_found_rests_until_reset() {
+        let mut throttled = Throttled::new(Counting { calls: 0 }, 1).with_until_found(true);
+        for _ in 0..3 {
+            throttled.classify(&Mat::default(), &MotionResult::none()).unwrap();
+        }
+        assert_eq!(throttled.classifier.calls, 1);
+
+        throttled.reset();
+        throttled.classify(&Mat::default(), &MotionResult::none()).unwrap();
+        assert_eq!(throttled.classifier.calls, 2);
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::Result;
+use opencv::prelude::*;
+use opencv::core::{convert_scale_abs, no_array, Ptr, CV_32F};
+use opencv::imgproc::{accumulate_weighted, threshold, THRESH_BINARY};
+use opencv::video::{
+    create_background_subtractor_knn, create_background_subtractor_mog2,
+    BackgroundSubtractor, BackgroundSubtractorKNN, BackgroundSubtractorMOG2,
+};
+use crate::cv::absdiff_prep;
+use super::matdiff::MatDiff;
+use super::result::MotionResult;
+
+
+// Value of shadow pixels in the foreground mask of a background subtractor
+const SHADOW_VALUE: f64 = 127.;
+
+
+/// Finds motion in a frame
+///
+/// Detectors are stateful: they are fed every frame in order and keep whatever history they need.
+pub trait MotionDetector {
+    fn detect(&mut self, frame: &Mat) -> Result<MotionResult>;
+}
+
+
+/// Detects motion as the foreground of an OpenCV background subtractor (MOG2 or KNN)
+///
+/// The subtractor is fed the blurred grayscale frame; its foreground mask goes through the
+/// threshold, dilate and contour steps of `diff`. Shadows are marked with 127 in the mask
+/// and are removed before that, so they never count as motion.
+pub struct BackgroundSubtractorDetector<T: BackgroundSubtractor> {
+    diff: MatDiff,
+    subtractor: T,
+    learning_rate: f64,
+}
+
+
+impl<T: BackgroundSubtractor> BackgroundSubtractorDetector<T> {
+    pub fn new(diff: MatDiff, subtractor: T, learning_rate: f64) -> Self {
+        Self { diff, subtractor, learning_rate }
+    }
+}
+
+
+impl BackgroundSubtractorDetector<Ptr<dyn BackgroundSubtractorMOG2>> {
+    pub fn mog2(
+        diff: MatDiff,
+        history: i32,
+        var_threshold: f64,
+        detect_shadows: bool,
+        learning_rate: f64) -> Result<Self>
+    {
+        let subtractor = create_background_subtractor_mog2(history, var_threshold, detect_shadows)?;
+        Ok(Self::new(diff, subtractor, learning_rate))
+    }
+}
+
+
+impl BackgroundSubtractorDetector<Ptr<dyn BackgroundSubtractorKNN>> {
+    pub fn knn(
+        diff: MatDiff,
+        history: i32,
+        dist2_threshold: f64,
+        detect_shadows: bool,
+        learning_rate: f64) -> Result<Self>
+    {
+        let subtractor = create_background_subtractor_knn(history, dist2_threshold, detect_shadows)?;
+        Ok(Self::new(diff, subtractor, learning_rate))
+    }
+}
+
+
+impl<T: BackgroundSubtractor> MotionDetector for BackgroundSubtractorDetector<T> {
+    fn detect(&mut self, frame: &Mat) -> Result<MotionResult> {
+        let prepared = self.diff.prepare_mat(frame)?;
+        let mut mask = Mat::default();
+        self.subtractor.apply(&prepared, &mut mask, self.learning_rate)?;
+        let mut foreground = Mat::default();
+        threshold(&mask, &mut foreground, SHADOW_VALUE, 255., THRESH_BINARY)?;
+        Ok(self.diff.motion(&foreground)?)
+    }
+}
+
+
+/// Compares every frame with a running average of the previous ones
+///
+/// Slow changes (lighting, shadows moving with the sun) are absorbed into the average,
+/// with `alpha` controlling how fast: the weight of the newest frame.
+pub struct RunningAverageDetector {
+    diff: MatDiff,
+    alpha: f64,
+    average: Option<Mat>,
+}
+
+
+impl RunningAverageDetector {
+    pub fn new(diff: MatDiff, alpha: f64) -> Self {
+        Self { diff, alpha, average: None }
+    }
+}
+
+
+impl MotionDetector for RunningAverageDetector {
+    fn detect(&mut self, frame: &Mat) -> Result<MotionResult> {
+        let prepared = self.diff.prepare_mat(frame)?;
+
+        let average = match &mut self.average {
+            Some(average) => average,
+            None => {
+                let mut average = Mat::default();
+                prepared.convert_to(&mut average, CV_32F, 1., 0.)?;
+                self.average = Some(average);
+                return Ok(MotionResult::none())
+            }
+        };
+
+        let mut background = Mat::default();
+        convert_scale_abs(average, &mut background, 1., 0.)?;
+        let result = self.diff.motion(&absdiff_prep(&background, &prepared)?)?;
+
+        accumulate_weighted(&prepared, average, self.alpha, &no_array())?;
+
+        Ok(result)
+    }
+}
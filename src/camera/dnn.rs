@@ -0,0 +1,159 @@
+use std::fs;
+use anyhow::{Error, Result};
+use opencv::prelude::*;
+use opencv::core::{Rect, Scalar, Size, CV_32F};
+use opencv::dnn::{blob_from_image, read_net, Net, DNN_BACKEND_OPENCV, DNN_TARGET_CPU};
+use log::*;
+use super::classifier::{pad, Classifier, Detection};
+use super::result::MotionResult;
+
+
+/// How crops are turned into the network input
+pub struct DnnInput {
+    pub size: Size,
+    // Pixel values are multiplied by this after the mean is subtracted
+    pub scale: f64,
+    pub mean: Scalar,
+    // The model expects RGB rather than OpenCV's BGR
+    pub swap_rb: bool,
+}
+
+
+/// Classifies motion regions with an image classification network (ONNX, Caffe, ...) on the CPU
+///
+/// Each region is classified on its own; the best class is reported if it is confident enough
+/// and passes the include and exclude lists.
+pub struct DnnClassifier {
+    net: Net,
+    labels: Vec<String>,
+    input: DnnInput,
+    // Apply softmax to the outputs, for models that give raw scores
+    softmax: bool,
+    min_confidence: f64,
+    padding: i32,
+    // Only these labels are reported, if not empty
+    include: Vec<String>,
+    // These labels are never reported
+    exclude: Vec<String>,
+}
+
+
+impl DnnClassifier {
+    /// Loads a model; `config` is the network description for frameworks that keep it apart
+    /// (e.g. a Caffe .prototxt), empty otherwise. `labels` has one class name per line.
+    pub fn new(model: &str, config: &str, labels: &str, input: DnnInput) -> Result<Self> {
+        info!("Loading classification model {}", model);
+        let mut net = read_net(model, config, "")?;
+        net.set_preferable_backend(DNN_BACKEND_OPENCV)?;
+        net.set_preferable_target(DNN_TARGET_CPU)?;
+
+        let labels: Vec<String> = fs::read_to_string(labels)?
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        Ok(Self {
+            net,
+            labels,
+            input,
+            softmax: true,
+            min_confidence: 0.5,
+            padding: 0,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        })
+    }
+
+    pub fn with_softmax(self, softmax: bool) -> Self {
+        Self { softmax, ..self }
+    }
+
+    pub fn with_min_confidence(self, min_confidence: f64) -> Self {
+        Self { min_confidence, ..self }
+    }
+
+    pub fn with_padding(self, padding: i32) -> Self {
+        Self { padding, ..self }
+    }
+
+    pub fn with_classes(self, include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { include, exclude, ..self }
+    }
+
+    fn classify_region(&mut self, frame: &Mat, region: Rect) -> Result<Option<Detection>> {
+        let crop = Mat::roi(frame, region)?;
+        let blob = blob_from_image(
+            &crop, self.input.scale, self.input.size, self.input.mean, self.input.swap_rb, false, CV_32F
+        )?;
+        self.net.set_input(&blob, "", 1., Scalar::default())?;
+        let output = self.net.forward_single("")?;
+
+        let mut scores: Vec<f32> = output.reshape(1, 1)?.data_typed::<f32>()?.to_vec();
+        if self.softmax {
+            softmax(&mut scores);
+        }
+
+        let (class, confidence) = match scores.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)) {
+            Some((class, confidence)) => (class, *confidence as f64),
+            None => return Ok(None),
+        };
+        let label = self.labels.get(class)
+            .ok_or_else(|| Error::msg(format!("No label for class {} in the label file", class)))?;
+
+        if confidence < self.min_confidence || !self.is_reported(label) {
+            return Ok(None)
+        }
+        Ok(Some(Detection {
+            label: label.clone(),
+            confidence,
+            region,
+        }))
+    }
+
+    fn is_reported(&self, label: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|l| l == label)) && !self.exclude.iter().any(|l| l == label)
+    }
+}
+
+
+impl Classifier for DnnClassifier {
+    fn classify(&mut self, frame: &Mat, motion: &MotionResult) -> Result<Vec<Detection>> {
+        let frame_size = frame.size()?;
+        let mut detections = Vec::new();
+        for region in &motion.regions {
+            let padded = pad(*region, self.padding, frame_size);
+            if padded.width == 0 || padded.height == 0 { continue }
+            detections.extend(self.classify_region(frame, padded)?);
+        }
+        Ok(detections)
+    }
+}
+
+
+fn softmax(scores: &mut [f32]) {
+    let max = scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let mut sum = 0.;
+    for score in scores.iter_mut() {
+        *score = (*score - max).exp();
+        sum += *score;
+    }
+    for score in scores.iter_mut() {
+        *score /= sum;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::softmax;
+
+
+    #[test]
+    fn softmax_sums_to_one_and_keeps_order() {
+        let mut scores = vec![1., 3., 2.];
+        softmax(&mut scores);
+        assert!((scores.iter().sum::<f32>() - 1.).abs() < 1e-6);
+        assert!(scores[1] > scores[2] && scores[2] > scores[0]);
+    }
+}
@@ -1,8 +1,11 @@
 use opencv::prelude::*;
-use opencv::imgproc::{bounding_rect, THRESH_BINARY};
+use opencv::core::{bitwise_and, count_non_zero, no_array, Point};
+use opencv::imgproc::{bounding_rect, contour_area, THRESH_BINARY};
 use opencv::Result;
-use opencv::types::VectorOfMat;
 use crate::cv::*;
+use super::detector::MotionDetector;
+use super::result::MotionResult;
+use super::zones::Zones;
 
 
 pub struct MatDiff {
@@ -11,6 +14,10 @@ pub struct MatDiff {
     pub threshold: Threshold,
     pub contours: FindContours,
     pub contour_area_threshold: i32,
+    pub zones: Zones,
+
+    // Previous prepared frame, when used as a MotionDetector
+    prev_frame: Option<Mat>,
 }
 
 
@@ -22,6 +29,8 @@ impl Default for MatDiff {
             threshold: Threshold::new(6_f64, 255_f64, THRESH_BINARY),
             contours: FindContours::default(),
             contour_area_threshold: 2000,
+            zones: Zones::default(),
+            prev_frame: None,
         }
     }
 }
@@ -35,36 +44,113 @@ impl MatDiff {
         contours: FindContours,
         contour_area_threshold: i32) -> Self
     {
-        Self { blur, dilate, threshold, contours, contour_area_threshold }
+        Self {
+            blur,
+            dilate,
+            threshold,
+            contours,
+            contour_area_threshold,
+            zones: Zones::default(),
+            prev_frame: None,
+        }
+    }
+
+    pub fn with_zones(self, zones: Zones) -> Self {
+        Self {
+            zones,
+            ..self
+        }
     }
 
-    fn prepare_mat(&self, src: &Mat) -> Result<Mat> {
+    /// Converts a frame to blurred grayscale
+    pub fn prepare_mat(&self, src: &Mat) -> Result<Mat> {
         self.blur.prep(
             &CvtColor::gray().prep(src)?
         )
     }
 
-    pub fn diff(&self, src1: &Mat, src2: &Mat) -> Result<bool> {
+    pub fn diff(&self, src1: &Mat, src2: &Mat) -> Result<MotionResult> {
 
         let mat1 = self.prepare_mat(src1)?;
         let mat2 = self.prepare_mat(src2)?;
 
         let diff = absdiff_prep(&mat1, &mat2)?;
-        let threshold = self.threshold.prep(&diff)?;
+        self.motion(&diff)
+    }
+
+    /// Looks for large enough changed areas in a difference (or foreground) mask
+    pub fn motion(&self, diff: &Mat) -> Result<MotionResult> {
+        let masked;
+        let diff = match self.zones.is_empty() {
+            true => diff,
+            false => {
+                masked = self.apply_zones(diff)?;
+                &masked
+            }
+        };
+        let threshold = self.threshold.prep(diff)?;
         let dilate = self.dilate.prep(&threshold)?;
         let contours = self.contours.prep(&dilate)?;
 
-        let result_contours: VectorOfMat = contours.iter().filter(
-            |x| {
-                match bounding_rect(x) {
-                    Ok(x) => x.area() > self.contour_area_threshold,
-                    Err(_) => false
+        let mut result = MotionResult {
+            changed_ratio: changed_ratio(&threshold)?,
+            ..MotionResult::none()
+        };
+
+        for contour in contours.iter() {
+            let rect = bounding_rect(&contour)?;
+            let center = Point::new(rect.x + rect.width / 2, rect.y + rect.height / 2);
+            let zones = self.zones.zones_at(center)?;
+
+            let area_threshold = zones.iter()
+                .filter_map(|z| z.sensitivity)
+                .min()
+                .unwrap_or(self.contour_area_threshold);
+            if rect.area() <= area_threshold {
+                continue
+            }
+
+            for zone in zones {
+                if !result.zones.contains(&zone.name) {
+                    result.zones.push(zone.name.clone());
                 }
             }
-        ).collect::<VectorOfMat>();
+            result.areas.push(contour_area(&contour, false)?);
+            result.regions.push(rect);
+            result.contours.push(contour);
+        }
+
+        result.mask = dilate;
+
+        Ok(result)
+    }
+
+    fn apply_zones(&self, diff: &Mat) -> Result<Mat> {
+        let mask = self.zones.mask(diff.size()?)?;
+        let mut result = Mat::default();
+        bitwise_and(diff, &mask, &mut result, &no_array())?;
+        Ok(result)
+    }
+}
+
 
-        Ok(!result_contours.is_empty())
+impl MotionDetector for MatDiff {
+    fn detect(&mut self, frame: &Mat) -> anyhow::Result<MotionResult> {
+        let prepared = self.prepare_mat(frame)?;
+        let result = match &self.prev_frame {
+            Some(prev_frame) => self.motion(&absdiff_prep(prev_frame, &prepared)?)?,
+            None => MotionResult::none(),
+        };
+        self.prev_frame = Some(prepared);
+        Ok(result)
+    }
+}
 
 
+fn changed_ratio(mask: &Mat) -> Result<f64> {
+    let total = mask.total()? as f64;
+    if total == 0. {
+        return Ok(0.)
     }
-}
\ No newline at end of file
+    Ok(count_non_zero(mask)? as f64 / total)
+}
@@ -1,7 +1,21 @@
 pub mod matdiff;
+pub mod detector;
+pub mod result;
+pub mod zones;
+pub mod overlay;
 pub mod handler;
 pub mod motion;
+pub mod classifier;
+pub mod dnn;
+pub mod tracker;
 
 pub use matdiff::*;
+pub use detector::*;
+pub use result::*;
+pub use zones::*;
+pub use overlay::*;
 pub use motion::*;
-pub use handler::*;
\ No newline at end of file
+pub use handler::*;
+pub use classifier::*;
+pub use dnn::*;
+pub use tracker::*;
\ No newline at end of file
@@ -4,7 +4,8 @@ pub mod state;
 mod state_watching;
 mod state_recording_motion;
 mod state_recording_idle;
+mod state_post_roll;
 
 pub use motion::MotionDetect;
-pub use writer::Writer;
+pub use writer::{Recording, Writer};
 pub use state::StatesConfig;
\ No newline at end of file
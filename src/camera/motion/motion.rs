@@ -1,50 +1,141 @@
 use anyhow::Result;
+use chrono::{DateTime, Local};
 use super::super::handler::Handler;
-use super::super::matdiff::MatDiff;
-use super::writer::Writer;
+use super::super::detector::MotionDetector;
+use super::super::result::MotionResult;
+use super::super::overlay::Overlay;
+use super::super::classifier::Classifier;
+use super::super::tracker::Tracker;
 use opencv::prelude::Mat;
 use crate::camera::motion::state::{StatesConfig};
-use crate::signals::*;
 use super::state::State;
 use super::state_watching::Watching;
-use crate::cv::VideoFileDirWriter;
 
 
 pub struct MotionDetect {
-    diff: MatDiff,
+    detector: Box<dyn MotionDetector>,
+    // Run on frames with motion, to tell what moved
+    classifiers: Vec<Box<dyn Classifier>>,
+    tracker: Option<Tracker>,
     states_config: StatesConfig,
-    prev_frame: Option<Mat>,
     state: Box<dyn State>,
+    last_result: MotionResult,
+    last_motion: Option<DateTime<Local>>,
+    // Drawn on recorded frames
+    overlay: Option<Overlay>,
+    // When false, motion is still detected but no new clips are started
+    recording_enabled: bool,
 }
 
 
 impl MotionDetect {
-    pub fn new(diff: MatDiff, states_config: StatesConfig) -> Self {
+    pub fn new(detector: Box<dyn MotionDetector>, states_config: StatesConfig) -> Self {
         Self {
-            diff,
+            detector,
+            classifiers: Vec::new(),
+            tracker: None,
             states_config,
-            prev_frame: None,
-            state: Box::new(Watching::new())
+            state: Box::new(Watching::new()),
+            last_result: MotionResult::none(),
+            last_motion: None,
+            overlay: None,
+            recording_enabled: true,
         }
     }
+
+    pub fn with_classifier(mut self, classifier: Box<dyn Classifier>) -> Self {
+        self.classifiers.push(classifier);
+        self
+    }
+
+    pub fn with_tracker(self, tracker: Tracker) -> Self {
+        Self {
+            tracker: Some(tracker),
+            ..self
+        }
+    }
+
+    pub fn with_overlay(self, overlay: Overlay) -> Self {
+        Self {
+            overlay: Some(overlay),
+            ..self
+        }
+    }
+
+    /// Detection result for the latest frame
+    pub fn last_result(&self) -> &MotionResult {
+        &self.last_result
+    }
+
+    /// When motion was last detected
+    pub fn last_motion(&self) -> Option<DateTime<Local>> {
+        self.last_motion
+    }
+
+    pub fn state_name(&self) -> &'static str {
+        self.state.name()
+    }
+
+    /// Takes the detector, classifiers, tracker, states config and overlay of `other`
+    ///
+    /// The current state is kept, so a clip in progress carries on with the new settings. The new
+    /// detector and tracker start without history, so only call this when the settings have changed.
+    pub fn reconfigure(&mut self, other: MotionDetect) {
+        self.detector = other.detector;
+        self.classifiers = other.classifiers;
+        self.tracker = other.tracker;
+        self.states_config = other.states_config;
+        self.overlay = other.overlay;
+    }
+
+    /// Finishes the clip in progress and goes back to watching
+    pub fn flush(&mut self) -> Result<()> {
+        let state = std::mem::replace(&mut self.state, Box::new(Watching::new()));
+        state.flush(&self.states_config)
+    }
+
+    /// Enables or disables recording; a clip in progress is finished normally
+    pub fn set_recording_enabled(&mut self, enabled: bool) {
+        self.recording_enabled = enabled;
+    }
+
+    /// Enables or disables notifications about finished clips
+    pub fn set_notify(&mut self, notify: bool) {
+        self.states_config.writer.set_notify(notify);
+    }
 }
 
 
 impl Handler for MotionDetect  {
     fn new_frame(mut self, frame: &Mat) -> Result<Self> {
-        let prev_frame: &Mat;
-
-        match &self.prev_frame {
-            Some(pf) => { prev_frame = pf; }
-            None => {
-                self.prev_frame = Some(frame.clone());
-                prev_frame = &self.prev_frame.as_ref().unwrap()
+        let mut result = self.detector.detect(frame)?;
+        if result.is_motion() {
+            self.last_motion = Some(Local::now());
+            for classifier in self.classifiers.iter_mut() {
+                let detections = classifier.classify(frame, &result)?;
+                result.detections.extend(detections);
             }
         }
+        // Frames without motion count too, so that tracks of objects gone can expire
+        if let Some(tracker) = &mut self.tracker {
+            result.tracks = tracker.update(&result.regions)?;
+        }
 
-        let frames_differ = self.diff.diff(&prev_frame, &frame)?;
+        let overlaid;
+        let frame = match &self.overlay {
+            Some(overlay) => {
+                overlaid = overlay.apply(frame, &result)?;
+                &overlaid
+            }
+            None => frame,
+        };
 
-        let new_state = self.state.handle(frame, &self.states_config, frames_differ);
+        let new_state = match self.recording_enabled {
+            true => self.state.handle(frame, &self.states_config, &result),
+            false => self.state.handle(frame, &self.states_config, &MotionResult::none()),
+        };
+        let motion = result.is_motion();
+        self.last_result = result;
 
         match new_state {
             Ok(state) => { self.state = state }
@@ -54,8 +145,13 @@ impl Handler for MotionDetect  {
             }
         }
 
-        self.prev_frame = Some(frame.clone());
+        // The motion event, and the clip of it if any, is over
+        if !motion && self.state.name() == "Watching" {
+            for classifier in self.classifiers.iter_mut() {
+                classifier.reset();
+            }
+        }
 
         Ok(self)
     }
-}
\ No newline at end of file
+}
@@ -2,6 +2,7 @@ use std::time::Duration;
 use anyhow::Result;
 use opencv::prelude::Mat;
 use super::writer::Writer;
+use crate::camera::result::MotionResult;
 
 
 pub type StateResult = Result<Box<dyn State>>;
@@ -16,18 +17,30 @@ pub struct StatesConfig {
     pub min_video_duration: Duration,
     pub max_video_duration: Duration,
     pub max_idle_gap: Duration,
+    // How much footage before the motion started to include
+    pub pre_roll: Duration,
+    // How much footage to include after the idle gap, or after max_video_duration is reached
+    pub post_roll: Duration,
 }
 
 
 pub trait State {
-    fn handle(self: Box<Self>, frame: &Mat, config: &StatesConfig, changed: bool) -> StateResult {
-        match changed {
-            true => self.handle_changed(frame, config),
+    /// Human-readable name of the state, e.g. for status reports
+    fn name(&self) -> &'static str;
+
+    fn handle(self: Box<Self>, frame: &Mat, config: &StatesConfig, motion: &MotionResult) -> StateResult {
+        match motion.is_motion() {
+            true => self.handle_changed(frame, config, motion),
             false => self.handle_unchanged(frame, config)
         }
     }
 
-    fn handle_changed(self: Box<Self>, frame: &Mat, config: &StatesConfig) -> StateResult;
+    fn handle_changed(self: Box<Self>, frame: &Mat, config: &StatesConfig, motion: &MotionResult) -> StateResult;
     fn handle_unchanged(self: Box<Self>, frame: &Mat, config: &StatesConfig) -> StateResult;
+
+    /// Finishes the clip in progress, if any, as no more frames are coming
+    fn flush(self: Box<Self>, _config: &StatesConfig) -> Result<()> {
+        Ok(())
+    }
 }
 
@@ -0,0 +1,60 @@
+use std::time::Instant;
+use anyhow::Result;
+use opencv::prelude::Mat;
+use log::*;
+use super::writer::Recording;
+use crate::camera::result::MotionResult;
+use super::state::*;
+use super::state_watching::Watching;
+
+
+/// Keeps recording for the post-roll once a clip is over, then finishes it
+///
+/// Motion seen meanwhile is recorded, but doesn't make the clip go on.
+pub struct PostRoll {
+    since: Instant,
+    recording: Recording,
+}
+
+
+impl PostRoll {
+    /// Enters the post-roll, or finishes the recording right away if there is none
+    pub fn start(recording: Recording, config: &StatesConfig) -> StateResult {
+        if config.post_roll.is_zero() {
+            config.writer.finish(recording)?;
+            return change_state(Watching::new())
+        }
+        debug!("Entering PostRoll state");
+        change_state(Self { since: Instant::now(), recording })
+    }
+
+    fn next(self: Box<Self>, config: &StatesConfig) -> StateResult {
+        if self.since.elapsed() < config.post_roll {
+            return Ok(self)
+        }
+        self.flush(config)?;
+        change_state(Watching::new())
+    }
+}
+
+
+impl State for PostRoll {
+    fn name(&self) -> &'static str {
+        "PostRoll"
+    }
+
+    fn handle_changed(mut self: Box<Self>, frame: &Mat, config: &StatesConfig, motion: &MotionResult) -> StateResult {
+        self.recording.write(frame)?;
+        self.recording.add_motion(motion);
+        self.next(config)
+    }
+
+    fn handle_unchanged(mut self: Box<Self>, frame: &Mat, config: &StatesConfig) -> StateResult {
+        self.recording.write(frame)?;
+        self.next(config)
+    }
+
+    fn flush(self: Box<Self>, config: &StatesConfig) -> Result<()> {
+        config.writer.finish(self.recording)
+    }
+}
@@ -1,57 +1,81 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use anyhow::Result;
 use opencv::prelude::Mat;
 use log::*;
+use super::writer::Recording;
+use crate::camera::result::MotionResult;
 use super::state::*;
+use super::state_post_roll::PostRoll;
 use super::state_recording_motion::RecordingMotion;
 use super::state_watching::Watching;
 
 
+/// Motion has stopped; the clip goes on if it resumes within the idle gap
 pub struct RecordingIdle {
     since: Instant,
-    frames: Vec<Mat>,
     collected_since: Instant,
-    collected_frames_total: Vec<Mat>,
+    recording: Recording,
 }
 
 
 impl RecordingIdle {
     pub fn new(
         collected_since: Instant,
-        collected_frames_total: Vec<Mat>) -> Self
+        recording: Recording) -> Self
     {
         debug!("Entering RecordingIdle state");
         Self {
             collected_since,
-            collected_frames_total,
+            recording,
             since: Instant::now(),
-            frames: Vec::default(),
         }
     }
+
+    fn is_long_enough(&self, config: &StatesConfig) -> bool {
+        self.motion_duration() > config.min_video_duration
+    }
+
+    /// From the first motion to the start of the idle gap
+    fn motion_duration(&self) -> Duration {
+        self.since.duration_since(self.collected_since)
+    }
 }
 
 
 impl State for RecordingIdle {
-    fn handle_changed(mut self: Box<Self>, frame: &Mat, _: &StatesConfig) -> StateResult {
-        self.frames.push(frame.clone());
+    fn name(&self) -> &'static str {
+        "RecordingIdle"
+    }
+
+    fn handle_changed(mut self: Box<Self>, frame: &Mat, _: &StatesConfig, motion: &MotionResult) -> StateResult {
+        self.recording.write(frame)?;
+        self.recording.add_motion(motion);
         change_state(
-            RecordingMotion::new(
-                self.collected_since,
-                [self.collected_frames_total, self.frames].concat()
-            )
+            RecordingMotion::new(self.collected_since, self.recording)
         )
     }
 
     fn handle_unchanged(mut self: Box<Self>, frame: &Mat, config: &StatesConfig) -> StateResult {
+        self.recording.write(frame)?;
         let elapsed = self.since.elapsed();
         if elapsed < config.max_idle_gap {
-            self.frames.push(frame.clone());
             return Ok(self)
         }
-        info!("Total time elapsed: {:?}\nTotal motion captured: {:?}", self.collected_since.elapsed(), self.collected_since.elapsed() - elapsed);
-        if self.collected_since.elapsed() - elapsed > config.min_video_duration {
-            config.writer.save(&self.collected_frames_total)?;
+        let motion_duration = self.motion_duration();
+        info!("Total time elapsed: {:?}\nTotal motion captured: {:?}", self.collected_since.elapsed(), motion_duration);
+        if motion_duration <= config.min_video_duration {
+            config.writer.discard(self.recording)?;
+            return change_state(Watching::new())
         }
-        change_state(Watching::new())
+        PostRoll::start(self.recording, config)
     }
 
-}
\ No newline at end of file
+    fn flush(self: Box<Self>, config: &StatesConfig) -> Result<()> {
+        if self.is_long_enough(config) {
+            config.writer.finish(self.recording)
+        } else {
+            config.writer.discard(self.recording)
+        }
+    }
+
+}
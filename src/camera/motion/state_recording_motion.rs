@@ -1,39 +1,50 @@
 use std::time::Instant;
+use anyhow::Result;
 use opencv::prelude::Mat;
 use log::*;
+use super::writer::Recording;
+use crate::camera::result::MotionResult;
 use super::state::*;
-use super::state_watching::Watching;
+use super::state_post_roll::PostRoll;
 use super::state_recording_idle::RecordingIdle;
 
 
 pub struct RecordingMotion {
     since: Instant,
-    frames: Vec<Mat>,
+    recording: Recording,
 }
 
 
 impl RecordingMotion {
-    pub fn new(since: Instant, frames: Vec<Mat>) -> Self {
+    pub fn new(since: Instant, recording: Recording) -> Self {
         debug!("(Re?)Entering RecordingMotion state; time elapsed: {:?}", since.elapsed());
-        Self { since, frames }
+        Self { since, recording }
     }
 }
 
 
 impl State for RecordingMotion {
-    fn handle_changed(mut self: Box<Self>, frame: &Mat, config: &StatesConfig) -> StateResult {
-        self.frames.push(frame.clone());
+    fn name(&self) -> &'static str {
+        "RecordingMotion"
+    }
+
+    fn handle_changed(mut self: Box<Self>, frame: &Mat, config: &StatesConfig, motion: &MotionResult) -> StateResult {
+        self.recording.write(frame)?;
+        self.recording.add_motion(motion);
 
         if self.since.elapsed() > config.max_video_duration {
-            config.writer.save(&self.frames)?;
-            return change_state(Watching::new())
+            return PostRoll::start(self.recording, config)
         }
 
         Ok(self)
     }
     fn handle_unchanged(self: Box<Self>, _: &Mat, _: &StatesConfig) -> StateResult {
         change_state(
-            RecordingIdle::new(self.since, self.frames)
+            RecordingIdle::new(self.since, self.recording)
         )
     }
-}
\ No newline at end of file
+
+    fn flush(self: Box<Self>, config: &StatesConfig) -> Result<()> {
+        config.writer.finish(self.recording)
+    }
+}
@@ -1,26 +1,65 @@
+use std::collections::VecDeque;
 use std::time::Instant;
 use opencv::prelude::Mat;
 use log::*;
+use crate::camera::result::MotionResult;
 use super::state::*;
 use super::state_recording_motion::RecordingMotion;
 
 
-pub struct Watching;
+pub struct Watching {
+    // Most recent frames, prepended to the recording once motion is detected
+    pre_roll: VecDeque<(Instant, Mat)>,
+}
 
 
 impl Watching {
     pub fn new() -> Self {
         debug!("Entering Watching state");
-        Watching {}
+        Watching { pre_roll: VecDeque::new() }
+    }
+
+    fn push_pre_roll(&mut self, frame: &Mat, config: &StatesConfig) {
+        if config.pre_roll.is_zero() { return }
+
+        let now = Instant::now();
+        self.pre_roll.push_back((now, frame.clone()));
+        while let Some((captured, _)) = self.pre_roll.front() {
+            if now.duration_since(*captured) <= config.pre_roll { break }
+            self.pre_roll.pop_front();
+        }
     }
 }
 
 
 impl State for Watching {
-    fn handle_changed(self: Box<Self>, frame: &Mat, _config: &StatesConfig) -> StateResult {
-        change_state(RecordingMotion::new(Instant::now(), vec![frame.clone()]))
+    fn name(&self) -> &'static str {
+        "Watching"
+    }
+
+    fn handle_changed(mut self: Box<Self>, frame: &Mat, config: &StatesConfig, motion: &MotionResult) -> StateResult {
+        let first_frame = self.pre_roll.front().map(|(_, f)| f).unwrap_or(frame);
+        // A clip that can't be opened (full disk, bad fourcc) is retried on the next motion frame
+        let mut recording = match config.writer.start(first_frame) {
+            Ok(recording) => recording,
+            Err(e) => {
+                error!("Cannot start a clip, still watching: {}", e);
+                self.push_pre_roll(frame, config);
+                return Ok(self)
+            }
+        };
+
+        debug!("Prepending {} pre-roll frames", self.pre_roll.len());
+        for (_, pre_roll_frame) in &self.pre_roll {
+            recording.write(pre_roll_frame)?;
+        }
+        recording.write(frame)?;
+        recording.add_motion(motion);
+
+        change_state(RecordingMotion::new(Instant::now(), recording))
     }
-    fn handle_unchanged(self: Box<Self>, _: &Mat, _config: &StatesConfig) -> StateResult {
+    fn handle_unchanged(mut self: Box<Self>, frame: &Mat, config: &StatesConfig) -> StateResult {
+        self.push_pre_roll(frame, config);
         Ok(self)
     }
 
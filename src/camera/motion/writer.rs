@@ -1,29 +1,166 @@
+use std::collections::BTreeMap;
+use std::path::Path;
 use anyhow::Result;
 use opencv::prelude::Mat;
 use log::*;
-use crate::cv::videoio::VideoFileDirWriter;
-use crate::cv::VideoSelectedFileWriterTrait;
-use crate::signals::{Sender, Signal};
+use serde::Serialize;
+use crate::camera::result::MotionResult;
+use crate::cv::videoio::{VideoFileDirWriter, VideoFileStream};
+use crate::fs::write_atomically;
+use crate::signals::{Capture, Label, Sender, Signal};
 
 
 pub struct Writer {
     writer: VideoFileDirWriter,
     sender: Sender,
+    camera: String,
+    notify: bool,
+    // Clips without any of these labels are discarded; empty keeps every clip
+    required_labels: Vec<String>,
+}
+
+
+/// A clip being recorded, along with what is known about the motion in it
+pub struct Recording {
+    stream: VideoFileStream,
+    zones: Vec<String>,
+    labels: Vec<Label>,
+    tracks: BTreeMap<u64, ClipTrack>,
+}
+
+
+/// An object tracked in a clip; frames are counted from the start of the clip
+#[derive(Serialize)]
+struct ClipTrack {
+    id: u64,
+    first_frame: usize,
+    last_frame: usize,
+    // (frame, x, y) of the object's centre
+    trajectory: Vec<(usize, i32, i32)>,
+}
+
+
+/// Saved next to the clip, with the same name and a .json extension
+#[derive(Serialize)]
+struct ClipMetadata<'a> {
+    camera: &'a str,
+    tracks: Vec<&'a ClipTrack>,
+}
+
+
+impl Recording {
+    pub fn write(&mut self, frame: &Mat) -> Result<()> {
+        self.stream.write(frame)
+    }
+
+    /// Takes note of the motion detected in a frame of this clip
+    pub fn add_motion(&mut self, motion: &MotionResult) {
+        for zone in &motion.zones {
+            if !self.zones.contains(zone) {
+                self.zones.push(zone.clone());
+            }
+        }
+        // The frame has just been written
+        let frame = self.stream.frames().saturating_sub(1);
+        for track in &motion.tracks {
+            let position = match track.trajectory.last() {
+                Some(position) => (frame, position.x, position.y),
+                None => continue,
+            };
+            let clip_track = self.tracks.entry(track.id).or_insert_with(|| ClipTrack {
+                id: track.id,
+                first_frame: frame,
+                last_frame: frame,
+                trajectory: Vec::new(),
+            });
+            clip_track.last_frame = frame;
+            clip_track.trajectory.push(position);
+        }
+        for detection in &motion.detections {
+            match self.labels.iter_mut().find(|l| l.name == detection.label) {
+                Some(label) => label.confidence = label.confidence.max(detection.confidence),
+                None => self.labels.push(Label {
+                    name: detection.label.clone(),
+                    confidence: detection.confidence,
+                }),
+            }
+        }
+    }
+
+    fn has_any_label(&self, names: &[String]) -> bool {
+        self.labels.iter().any(|label| names.contains(&label.name))
+    }
 }
 
 
 impl Writer {
-    pub fn new(writer: VideoFileDirWriter, sender: Sender) -> Self {
+    pub fn new(writer: VideoFileDirWriter, sender: Sender, camera: &str) -> Self {
         Self {
             writer,
-            sender
+            sender,
+            camera: camera.to_string(),
+            notify: true,
+            required_labels: Vec::new(),
         }
     }
 
-    pub fn save(&self, content: &Vec<Mat>) -> Result<()> {
-        debug!("Saving content of ({} frames)", content.len());
-        let saved = self.writer.save(content)?;
-        self.sender.send(Signal::MotionCaptured(saved))?;
+    /// Keeps only clips in which one of `labels` was recognised
+    pub fn with_required_labels(self, labels: Vec<String>) -> Self {
+        Self {
+            required_labels: labels,
+            ..self
+        }
+    }
+
+    /// Whether clips finished from now on should be notified about
+    pub fn set_notify(&mut self, notify: bool) {
+        self.notify = notify;
+    }
+
+    /// Opens a new clip; frames are appended to it as they come
+    pub fn start(&self, first_frame: &Mat) -> Result<Recording> {
+        Ok(Recording {
+            stream: self.writer.open(first_frame)?,
+            zones: Vec::new(),
+            labels: Vec::new(),
+            tracks: BTreeMap::new(),
+        })
+    }
+
+    /// Finalises the clip and announces it
+    pub fn finish(&self, recording: Recording) -> Result<()> {
+        if !self.required_labels.is_empty() && !recording.has_any_label(&self.required_labels) {
+            info!("Nothing of {:?} recognised, discarding {}", self.required_labels, recording.stream.path());
+            return recording.stream.discard()
+        }
+
+        debug!("Finishing clip of ({} frames)", recording.stream.frames());
+        let saved = recording.stream.finish()?;
+        if !recording.tracks.is_empty() {
+            self.save_metadata(&saved, &recording.tracks)?;
+        }
+        self.sender.send(Signal::MotionCaptured(Capture {
+            camera: self.camera.clone(),
+            path: saved,
+            zones: recording.zones,
+            labels: recording.labels,
+            notify: self.notify,
+        }))?;
         Ok(())
     }
+
+    fn save_metadata(&self, clip: &str, tracks: &BTreeMap<u64, ClipTrack>) -> Result<()> {
+        let metadata = ClipMetadata {
+            camera: &self.camera,
+            tracks: tracks.values().collect(),
+        };
+        let path = Path::new(clip).with_extension("json");
+        write_atomically(&path, |file| Ok(serde_json::to_writer_pretty(file, &metadata)?))
+    }
+
+    /// Drops a clip that turned out too short
+    pub fn discard(&self, recording: Recording) -> Result<()> {
+        debug!("Discarding clip of ({} frames) at {}", recording.stream.frames(), recording.stream.path());
+        recording.stream.discard()
+    }
 }
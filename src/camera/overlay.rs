@@ -0,0 +1,63 @@
+use chrono::Local;
+use opencv::prelude::*;
+use opencv::core::Point;
+use opencv::Result;
+use crate::cv::{DrawContours, DrawRectangles, PutText};
+use super::result::MotionResult;
+
+
+/// Burns the capture time, camera name and (optionally) the detected motion into frames
+pub struct Overlay {
+    camera: String,
+    timestamp_format: String,
+    text: PutText,
+    position: Point,
+    boxes: Option<DrawRectangles>,
+    contours: Option<DrawContours>,
+}
+
+
+impl Overlay {
+    pub fn new(camera: &str, timestamp_format: &str, text: PutText, position: Point) -> Self {
+        Self {
+            camera: camera.to_string(),
+            timestamp_format: timestamp_format.to_string(),
+            text,
+            position,
+            boxes: None,
+            contours: None,
+        }
+    }
+
+    pub fn with_boxes(self, boxes: DrawRectangles) -> Self {
+        Self {
+            boxes: Some(boxes),
+            ..self
+        }
+    }
+
+    pub fn with_contours(self, contours: DrawContours) -> Self {
+        Self {
+            contours: Some(contours),
+            ..self
+        }
+    }
+
+    /// Returns a copy of the frame with the overlay drawn on it
+    pub fn apply(&self, frame: &Mat, motion: &MotionResult) -> Result<Mat> {
+        let mut result = frame.clone();
+
+        if let Some(contours) = &self.contours {
+            contours.dest(&mut result, &motion.contours)?;
+        }
+        if let Some(boxes) = &self.boxes {
+            boxes.dest(&mut result, &motion.regions)?;
+        }
+
+        let timestamp = Local::now().format(&self.timestamp_format);
+        let text = format!("{} {}", self.camera, timestamp);
+        self.text.dest(&mut result, &text, self.position)?;
+
+        Ok(result)
+    }
+}
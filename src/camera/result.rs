@@ -0,0 +1,51 @@
+use opencv::prelude::*;
+use opencv::core::Rect;
+use opencv::types::VectorOfMat;
+use super::classifier::Detection;
+use super::tracker::Track;
+
+
+/// What a motion detector found in a frame
+#[derive(Clone, Default)]
+pub struct MotionResult {
+    // Contours of the changed areas that passed the area threshold
+    pub contours: VectorOfMat,
+
+    // Bounding rectangles of `contours`
+    pub regions: Vec<Rect>,
+
+    // Areas of `contours`, in pixels
+    pub areas: Vec<f64>,
+
+    // Names of the include zones the contours are in
+    pub zones: Vec<String>,
+
+    // What the classifiers recognised in the motion regions
+    pub detections: Vec<Detection>,
+
+    // Objects followed across frames, if tracking is enabled
+    pub tracks: Vec<Track>,
+
+    // Share of the frame's pixels that changed, 0..1 (before the area threshold is applied)
+    pub changed_ratio: f64,
+
+    // Thresholded and dilated difference mask the contours were extracted from
+    pub mask: Mat,
+}
+
+
+impl MotionResult {
+    /// Result for a frame that could not be compared with anything yet
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn is_motion(&self) -> bool {
+        !self.regions.is_empty()
+    }
+
+    /// Total area of the detected contours, in pixels
+    pub fn total_area(&self) -> f64 {
+        self.areas.iter().sum()
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::Result;
+use opencv::prelude::*;
+use opencv::core::{set_identity, Point, Rect, Scalar, CV_32F};
+use opencv::video::KalmanFilter;
+
+
+// Longest trajectory kept per track, in points
+const MAX_TRAJECTORY: usize = 300;
+
+
+/// How motion regions are matched with the tracks of the previous frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Association {
+    // Overlap of the boxes (intersection over union) of at least this much
+    Iou(f64),
+    // Distance between box centres of at most this many pixels
+    Centroid(f64),
+}
+
+
+/// An object followed across frames
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub id: u64,
+    // Latest box of the object
+    pub region: Rect,
+    // Box centres, oldest first
+    pub trajectory: Vec<Point>,
+    // Frames since the track was created
+    pub age: u32,
+    // Frames the object was seen in
+    pub hits: u32,
+    // Consecutive frames the object hasn't been seen in
+    pub missed: u32,
+}
+
+
+struct TrackState {
+    track: Track,
+    kalman: Option<KalmanFilter>,
+}
+
+
+/// Associates motion regions across frames into tracks with stable IDs
+///
+/// Matching is greedy, best pairs first. With Kalman smoothing the position of every track is
+/// predicted with a constant velocity model before matching, and the trajectory holds the
+/// filtered centres.
+pub struct Tracker {
+    association: Association,
+    // Tracks unseen for more frames than this are dropped
+    max_missed: u32,
+    kalman: bool,
+    tracks: Vec<TrackState>,
+    next_id: u64,
+}
+
+
+impl Tracker {
+    pub fn new(association: Association, max_missed: u32) -> Self {
+        Self {
+            association,
+            max_missed,
+            kalman: false,
+            tracks: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn with_kalman(self, kalman: bool) -> Self {
+        Self { kalman, ..self }
+    }
+
+    /// Feeds the motion regions of the next frame; returns the tracks seen in it
+    pub fn update(&mut self, regions: &[Rect]) -> Result<Vec<Track>> {
+        for state in self.tracks.iter_mut() {
+            if let Some(kalman) = &mut state.kalman {
+                let predicted = kalman.predict(&Mat::default())?;
+                let center = Point::new(*predicted.at_2d::<f32>(0, 0)? as i32, *predicted.at_2d::<f32>(1, 0)? as i32);
+                state.track.region = centered(state.track.region, center);
+            }
+        }
+
+        let mut pairs: Vec<(usize, usize, f64)> = Vec::new();
+        for (t, state) in self.tracks.iter().enumerate() {
+            for (r, region) in regions.iter().enumerate() {
+                if let Some(score) = self.score(&state.track.region, region) {
+                    pairs.push((t, r, score));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+        let mut track_matched = vec![false; self.tracks.len()];
+        let mut region_matched = vec![false; regions.len()];
+        for (t, r, _) in pairs {
+            if track_matched[t] || region_matched[r] { continue }
+            track_matched[t] = true;
+            region_matched[r] = true;
+            observe(&mut self.tracks[t], regions[r])?;
+        }
+
+        for (state, matched) in self.tracks.iter_mut().zip(&track_matched) {
+            state.track.age += 1;
+            if !matched {
+                state.track.missed += 1;
+            }
+        }
+        let max_missed = self.max_missed;
+        self.tracks.retain(|state| state.track.missed <= max_missed);
+
+        for (region, matched) in regions.iter().zip(region_matched) {
+            if matched { continue }
+            let state = self.start_track(*region)?;
+            self.tracks.push(state);
+        }
+
+        Ok(self.tracks.iter()
+            .filter(|state| state.track.missed == 0)
+            .map(|state| state.track.clone())
+            .collect())
+    }
+
+    /// Higher is a better match; None if the pair doesn't match at all
+    fn score(&self, track: &Rect, region: &Rect) -> Option<f64> {
+        match self.association {
+            Association::Iou(min_iou) => {
+                let iou = iou(track, region);
+                if iou >= min_iou { Some(iou) } else { None }
+            }
+            Association::Centroid(max_distance) => {
+                let distance = distance(center(track), center(region));
+                if distance <= max_distance { Some(-distance) } else { None }
+            }
+        }
+    }
+
+    fn start_track(&mut self, region: Rect) -> Result<TrackState> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let kalman = match self.kalman {
+            true => Some(kalman_filter(center(&region))?),
+            false => None,
+        };
+        Ok(TrackState {
+            track: Track {
+                id,
+                region,
+                trajectory: vec![center(&region)],
+                age: 0,
+                hits: 1,
+                missed: 0,
+            },
+            kalman,
+        })
+    }
+}
+
+
+fn observe(state: &mut TrackState, region: Rect) -> Result<()> {
+    let mut position = center(&region);
+    if let Some(kalman) = &mut state.kalman {
+        let measurement = Mat::from_slice_2d(&[[position.x as f32], [position.y as f32]])?;
+        let corrected = kalman.correct(&measurement)?;
+        position = Point::new(*corrected.at_2d::<f32>(0, 0)? as i32, *corrected.at_2d::<f32>(1, 0)? as i32);
+    }
+
+    let track = &mut state.track;
+    track.region = centered(region, position);
+    track.trajectory.push(position);
+    if track.trajectory.len() > MAX_TRAJECTORY {
+        track.trajectory.remove(0);
+    }
+    track.hits += 1;
+    track.missed = 0;
+    Ok(())
+}
+
+
+/// Constant velocity model: the state is (x, y, dx, dy), the measurement (x, y)
+fn kalman_filter(position: Point) -> Result<KalmanFilter> {
+    let mut kalman = KalmanFilter::new(4, 2, 0, CV_32F)?;
+    kalman.set_transition_matrix(Mat::from_slice_2d(&[
+        [1f32, 0., 1., 0.],
+        [0., 1., 0., 1.],
+        [0., 0., 1., 0.],
+        [0., 0., 0., 1.],
+    ])?);
+    kalman.set_measurement_matrix(Mat::from_slice_2d(&[
+        [1f32, 0., 0., 0.],
+        [0., 1., 0., 0.],
+    ])?);
+    set_identity(&mut kalman.process_noise_cov(), Scalar::all(1e-2))?;
+    set_identity(&mut kalman.measurement_noise_cov(), Scalar::all(1e-1))?;
+    set_identity(&mut kalman.error_cov_post(), Scalar::all(1.))?;
+    kalman.set_state_post(Mat::from_slice_2d(&[
+        [position.x as f32], [position.y as f32], [0.], [0.],
+    ])?);
+    Ok(kalman)
+}
+
+
+fn center(rect: &Rect) -> Point {
+    Point::new(rect.x + rect.width / 2, rect.y + rect.height / 2)
+}
+
+
+fn centered(rect: Rect, center: Point) -> Rect {
+    Rect::new(center.x - rect.width / 2, center.y - rect.height / 2, rect.width, rect.height)
+}
+
+
+fn distance(a: Point, b: Point) -> f64 {
+    (((a.x - b.x) as f64).powi(2) + ((a.y - b.y) as f64).powi(2)).sqrt()
+}
+
+
+fn iou(a: &Rect, b: &Rect) -> f64 {
+    let x1 = a.x.max(b.x);
+    let y1 = a.y.max(b.y);
+    let x2 = (a.x + a.width).min(b.x + b.width);
+    let y2 = (a.y + a.height).min(b.y + b.height);
+    if x2 <= x1 || y2 <= y1 {
+        return 0.
+    }
+    let intersection = ((x2 - x1) * (y2 - y1)) as f64;
+    let union = (a.width * a.height + b.width * b.height) as f64 - intersection;
+    intersection / union
+}
+
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::Rect;
+    use super::{Association, Tracker};
+
+
+    #[test]
+    fn keeps_ids_of_moving_objects() {
+        let mut tracker = Tracker::new(Association::Iou(0.3), 2);
+
+        let first = tracker.update(&[Rect::new(0, 0, 20, 20), Rect::new(100, 100, 20, 20)]).unwrap();
+        assert_eq!(first.iter().map(|t| t.id).collect::<Vec<_>>(), vec![1, 2]);
+
+        let second = tracker.update(&[Rect::new(104, 102, 20, 20), Rect::new(3, 2, 20, 20)]).unwrap();
+        let by_position: Vec<(u64, i32)> = second.iter().map(|t| (t.id, t.region.x)).collect();
+        assert!(by_position.contains(&(1, 3)));
+        assert!(by_position.contains(&(2, 104)));
+        assert_eq!(second.iter().find(|t| t.id == 1).unwrap().trajectory.len(), 2);
+    }
+
+    #[test]
+    fn drops_tracks_unseen_for_too_long() {
+        let mut tracker = Tracker::new(Association::Centroid(30.), 1);
+        tracker.update(&[Rect::new(0, 0, 20, 20)]).unwrap();
+        assert!(tracker.update(&[]).unwrap().is_empty());
+        tracker.update(&[]).unwrap();
+
+        let tracks = tracker.update(&[Rect::new(0, 0, 20, 20)]).unwrap();
+        assert_eq!(tracks[0].id, 2);
+    }
+}
@@ -0,0 +1,84 @@
+use opencv::prelude::*;
+use opencv::core::{Point, Point2f, Scalar, Size, CV_8UC1};
+use opencv::imgproc::{fill_poly, point_polygon_test, LINE_8};
+use opencv::types::{VectorOfPoint, VectorOfVectorOfPoint};
+use opencv::Result;
+use serde::Deserialize;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all="lowercase")]
+pub enum ZoneKind {
+    // Motion is only detected inside include zones (if there are any)
+    Include,
+    // Motion inside exclude zones is ignored
+    Exclude,
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Zone {
+    pub name: String,
+    pub kind: ZoneKind,
+    pub polygon: Vec<Point>,
+    // Minimum area of detected motion inside this zone, overriding the global one
+    pub sensitivity: Option<i32>,
+}
+
+
+impl Zone {
+    pub fn contains(&self, point: Point) -> Result<bool> {
+        let polygon = VectorOfPoint::from_iter(self.polygon.iter().copied());
+        let pt = Point2f::new(point.x as f32, point.y as f32);
+        Ok(point_polygon_test(&polygon, pt, false)? >= 0.)
+    }
+}
+
+
+/// Include/exclude zones of a camera's frame
+#[derive(Clone, Debug, Default)]
+pub struct Zones {
+    zones: Vec<Zone>,
+}
+
+
+impl Zones {
+    pub fn new(zones: Vec<Zone>) -> Self {
+        Self { zones }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+
+    /// Builds a mask of the given size: 255 where motion counts, 0 where it is ignored
+    pub fn mask(&self, size: Size) -> Result<Mat> {
+        let has_includes = self.zones.iter().any(|z| z.kind == ZoneKind::Include);
+        let base = if has_includes { 0. } else { 255. };
+        let mut mask = Mat::new_size_with_default(size, CV_8UC1, Scalar::all(base))?;
+
+        // Exclusions go last so they win where zones overlap
+        for kind in [ZoneKind::Include, ZoneKind::Exclude] {
+            let color = if kind == ZoneKind::Include { 255. } else { 0. };
+            let polygons = self.zones.iter()
+                .filter(|z| z.kind == kind)
+                .map(|z| VectorOfPoint::from_iter(z.polygon.iter().copied()))
+                .collect::<VectorOfVectorOfPoint>();
+            if polygons.is_empty() { continue }
+            fill_poly(&mut mask, &polygons, Scalar::all(color), LINE_8, 0, Point::default())?;
+        }
+
+        Ok(mask)
+    }
+
+    /// Include zones that contain the point
+    pub fn zones_at(&self, point: Point) -> Result<Vec<&Zone>> {
+        let mut result = Vec::new();
+        for zone in self.zones.iter().filter(|z| z.kind == ZoneKind::Include) {
+            if zone.contains(point)? {
+                result.push(zone);
+            }
+        }
+        Ok(result)
+    }
+}
@@ -1,11 +1,248 @@
+use std::collections::HashMap;
+use std::fs;
+use anyhow::Result;
+use chrono::{NaiveTime, Weekday};
 use opencv::videoio::VideoWriter;
 use serde::Deserialize;
-use crate::config::deserialize_fourcc;
+use opencv::core::{Point, Scalar};
+use crate::camera::{Zone, ZoneKind};
+use crate::config::{
+    deserialize_bgr_color, deserialize_fourcc, deserialize_point, deserialize_points, deserialize_time,
+    deserialize_weekdays,
+};
 
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    // Run without preview windows
+    pub headless: bool,
+
+    // One entry per camera, each running its own motion detection pipeline
+    pub cameras: Vec<DiffConfig>,
+
+    // What disarming does, and when cameras are armed automatically
+    pub arming: ArmingConfig,
+
+    // Who may talk to the bot
+    pub telegram: TelegramConfig,
+
+    // Other ways to deliver alerts
+    pub notify: NotifyConfig,
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TelegramConfig {
+    // Run the bot at all
+    pub enabled: bool,
+
+    // Chats the bot answers in. If empty, the CHAT_ID environment variable is used as an admin chat
+    pub chats: Vec<ChatConfig>,
+
+    // Per-user roles, granted in any configured chat
+    pub users: Vec<UserConfig>,
+
+    // File keeping undelivered notifications across restarts
+    pub outbox: String,
+}
+
+
+impl Default for TelegramConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            chats: Vec::new(),
+            users: Vec::new(),
+            outbox: "outbox.jsonl".to_owned(),
+        }
+    }
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ChatConfig {
+    pub id: i64,
+
+    #[serde(default)]
+    pub role: Role,
+
+    // Receive motion alerts and arming announcements
+    #[serde(default="default_subscribed")]
+    pub subscribed: bool,
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct UserConfig {
+    pub id: u64,
+    pub role: Role,
+}
+
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all="lowercase")]
+pub enum Role {
+    // Receives alerts, asks for /status and /snapshot
+    #[default]
+    Viewer,
+    // Also stops/starts and arms/disarms cameras
+    Admin,
+}
+
+
+fn default_subscribed() -> bool { true }
+
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub webhooks: Vec<WebhookConfig>,
+    pub email: Vec<EmailConfig>,
+    pub commands: Vec<CommandConfig>,
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct WebhookConfig {
+    #[serde(default="default_enabled")]
+    pub enabled: bool,
+
+    // Receives a JSON POST for every alert
+    pub url: String,
+
+    // Extra request headers, e.g. Authorization
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    // Seconds
+    #[serde(default="default_notify_timeout")]
+    pub timeout: u64,
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct EmailConfig {
+    #[serde(default="default_enabled")]
+    pub enabled: bool,
+
+    // SMTP server, connected to with STARTTLS
+    pub server: String,
+    #[serde(default="default_smtp_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+
+    pub from: String,
+    pub to: Vec<String>,
+
+    // Attach the clip to motion alerts, unless it is larger than max_attachment_size (MB)
+    #[serde(default="default_enabled")]
+    pub attach_clip: bool,
+    #[serde(default="default_max_attachment_size")]
+    pub max_attachment_size: u64,
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct CommandConfig {
+    #[serde(default="default_enabled")]
+    pub enabled: bool,
+
+    // Program to run for every alert; details are passed in environment variables
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    // Seconds before the command is killed
+    #[serde(default="default_notify_timeout")]
+    pub timeout: u64,
+}
+
+
+fn default_enabled() -> bool { true }
+fn default_notify_timeout() -> u64 { 10 }
+fn default_smtp_port() -> u16 { 587 }
+fn default_max_attachment_size() -> u64 { 20 }
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ArmingConfig {
+    // Whether cameras start armed
+    pub armed_on_start: bool,
+
+    // Disarmed cameras keep watching, but don't save clips
+    pub suppress_recordings: bool,
+
+    // Disarmed cameras keep recording, but don't send notifications
+    pub suppress_notifications: bool,
+
+    // Weekly windows when cameras are armed; outside of them they are disarmed.
+    // Empty means no automatic arming.
+    pub schedule: Vec<ScheduleWindowConfig>,
+}
+
+
+impl Default for ArmingConfig {
+    fn default() -> Self {
+        Self {
+            armed_on_start: true,
+            suppress_recordings: false,
+            suppress_notifications: true,
+            schedule: Vec::new(),
+        }
+    }
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ScheduleWindowConfig {
+    // e.g. ["mon", "tue", "wed", "thu", "fri"]
+    #[serde(deserialize_with="deserialize_weekdays")]
+    pub days: Vec<Weekday>,
+
+    // "HH:MM"; a window ending before it starts runs past midnight
+    #[serde(deserialize_with="deserialize_time")]
+    pub from: NaiveTime,
+
+    #[serde(deserialize_with="deserialize_time")]
+    pub to: NaiveTime,
+}
+
+
+impl Config {
+    /// Reads the config from a toml file
+    ///
+    /// A file without a `[[cameras]]` array is treated as a single camera config.
+    /// `{camera}` in the output folder and filename format is replaced with the camera name.
+    pub fn load(path: &str) -> Result<Self> {
+        let config_toml = fs::read_to_string(path)?;
+        let mut config: Config = toml::from_str(&config_toml)?;
+        if config.cameras.is_empty() {
+            config.cameras.push(toml::from_str(&config_toml)?);
+        }
+        for camera in config.cameras.iter_mut() {
+            let output = &mut camera.output;
+            output.result_folder = output.result_folder.replace("{camera}", &camera.name);
+            // The format goes through strftime, and must stay a single file name
+            let name = camera.name.replace('%', "%%").replace('/', "_");
+            output.result_filename_format = output.result_filename_format.replace("{camera}", &name);
+        }
+        Ok(config)
+    }
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
 #[serde(default)]
 pub struct DiffConfig {
+    // Camera name, used in window titles and notifications
+    pub name: String,
+
     // Preprocessing blur radius
     // # note: must be an odd number
     pub blur_radius: i32,
@@ -30,13 +267,41 @@ pub struct DiffConfig {
     // Maximum allowed gap between motion episodes (without interrupting recording), in seconds
     pub max_idle_gap: u64,
 
+    // Footage kept from before the motion started, in seconds
+    pub pre_roll_duration: u64,
+
+    // Footage kept after the idle gap, or after max_video_duration, in seconds
+    pub post_roll_duration: u64,
+
     pub output: OutputFileConfig,
+
+    // Where the frames come from
+    pub source: SourceConfig,
+
+    // What to do when a live source drops out
+    pub reconnect: ReconnectConfig,
+
+    // How motion is detected
+    pub detector: DetectorConfig,
+
+    // Polygonal areas where motion is (or is not) detected
+    pub zones: Vec<ZoneConfig>,
+
+    // Looking for people in the motion regions
+    pub people: PeopleConfig,
+
+    // Telling what moved with a classification network
+    pub classifier: ClassifierConfig,
+
+    // Following moving objects across frames
+    pub tracking: TrackingConfig,
 }
 
 
 impl Default for DiffConfig {
     fn default() -> Self {
         Self {
+            name: "camera".to_owned(),
             blur_radius: 3,
             blug_sigma: 3.5,
             dilate_radius: 6,
@@ -46,13 +311,22 @@ impl Default for DiffConfig {
             min_video_duration: 2,
             max_video_duration: 15,
             max_idle_gap: 2,
-            output: OutputFileConfig::default()
+            pre_roll_duration: 2,
+            post_roll_duration: 1,
+            output: OutputFileConfig::default(),
+            source: SourceConfig::default(),
+            reconnect: ReconnectConfig::default(),
+            detector: DetectorConfig::default(),
+            zones: Vec::new(),
+            people: PeopleConfig::default(),
+            classifier: ClassifierConfig::default(),
+            tracking: TrackingConfig::default(),
         }
     }
 }
 
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Debug, PartialEq)]
 #[serde(default)]
 pub struct OutputFileConfig {
     #[serde(deserialize_with="deserialize_fourcc")]
@@ -60,21 +334,410 @@ pub struct OutputFileConfig {
 
     pub fps: f64,
 
-    // result video filename format
+    // result video filename format; {camera} is the camera name
     pub result_filename_format: String,
 
-    // a folder for resulting video files
-    pub result_folder: String
+    // a folder for resulting video files; {camera} is the camera name
+    pub result_folder: String,
+
+    // Text and motion drawn on the saved frames
+    pub overlay: OverlayConfig,
+
+    // When to delete old clips from result_folder
+    pub retention: RetentionConfig,
 }
 
 
 impl Default for OutputFileConfig {
     fn default() -> Self {
         Self {
-            fourcc: VideoWriter::fourcc('m', 'p', 'v', '4').unwrap(),
+            fourcc: VideoWriter::fourcc('m', 'p', '4', 'v').unwrap(),
             fps: 24.,
-            result_filename_format: "%Y-%m-%d-%H-%M-%S.mp4".to_owned(),
-            result_folder: "output".to_owned()
+            // Cameras share the folder, so the name keeps their clips apart
+            result_filename_format: "{camera}-%Y-%m-%d-%H-%M-%S.mp4".to_owned(),
+            result_folder: "output".to_owned(),
+            overlay: OverlayConfig::default(),
+            retention: RetentionConfig::default(),
         }
     }
-}
\ No newline at end of file
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RetentionConfig {
+    // Maximum age of a clip, in hours
+    pub max_age: Option<u64>,
+
+    // Maximum total size of the folder, in megabytes
+    pub max_total_size: Option<u64>,
+
+    // Maximum number of files in the folder
+    pub max_files: Option<usize>,
+
+    // How often the folder is checked, in seconds
+    pub check_interval: u64,
+}
+
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            max_age: None,
+            max_total_size: None,
+            max_files: None,
+            check_interval: 600,
+        }
+    }
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct OverlayConfig {
+    pub enabled: bool,
+
+    // Also draw the overlay on snapshots
+    pub on_snapshots: bool,
+
+    // chrono format of the capture time
+    pub timestamp_format: String,
+
+    pub font_scale: f64,
+
+    // Bottom-left corner of the text, in pixels
+    #[serde(deserialize_with="deserialize_point")]
+    pub position: Point,
+
+    // Text colour, "#RRGGBBAA"
+    #[serde(deserialize_with="deserialize_bgr_color")]
+    pub color: Scalar,
+
+    pub thickness: i32,
+
+    // Draw bounding boxes of the detected motion
+    pub draw_boxes: bool,
+
+    // Draw outlines of the detected motion
+    pub draw_contours: bool,
+
+    // Colour of the boxes and outlines, "#RRGGBBAA"
+    #[serde(deserialize_with="deserialize_bgr_color")]
+    pub motion_color: Scalar,
+}
+
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            on_snapshots: true,
+            timestamp_format: "%Y-%m-%d %H:%M:%S".to_owned(),
+            font_scale: 0.5,
+            position: Point::new(10, 20),
+            color: Scalar::new(255., 255., 255., 255.),
+            thickness: 1,
+            draw_boxes: false,
+            draw_contours: false,
+            motion_color: Scalar::new(0., 255., 0., 255.),
+        }
+    }
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag="type", rename_all="lowercase")]
+pub enum SourceConfig {
+    // Local capture device, e.g. /dev/video0
+    Device {
+        #[serde(default)]
+        index: i32,
+        #[serde(default="default_frame_width")]
+        width: i32,
+        #[serde(default="default_frame_height")]
+        height: i32,
+    },
+
+    // Network stream: rtsp://..., http://.../mjpeg
+    Url {
+        url: String,
+    },
+
+    // Recorded video file
+    File {
+        path: String,
+    },
+
+    // A directory of still images, played back in filename order
+    Images {
+        folder: String,
+        #[serde(default="default_images_fps")]
+        fps: f64,
+    },
+}
+
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        Self::Device {
+            index: 0,
+            width: default_frame_width(),
+            height: default_frame_height(),
+        }
+    }
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PeopleConfig {
+    pub enabled: bool,
+
+    // Minimum HOG detector score of a person
+    pub min_confidence: f64,
+
+    // Added around motion regions before looking for people, in pixels
+    pub padding: i32,
+
+    // Only save and notify about clips with a person in them
+    pub require: bool,
+
+    // Look for people on every n-th motion frame only, and not again once one was found in the event
+    pub every_n_frames: u32,
+}
+
+
+impl Default for PeopleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_confidence: 0.5,
+            padding: 16,
+            require: false,
+            every_n_frames: 5,
+        }
+    }
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ClassifierConfig {
+    pub enabled: bool,
+
+    // Model file: .onnx, .caffemodel, ...
+    pub model: String,
+
+    // Network description for frameworks that keep it apart from the weights, e.g. a Caffe .prototxt
+    pub config: String,
+
+    // Class names, one per line, in the order of the model outputs
+    pub labels: String,
+
+    // Input size of the network, in pixels
+    pub input_width: i32,
+    pub input_height: i32,
+
+    // Pixel values are (value - mean) * scale, channels in RGB order if swap_rb
+    pub scale: f64,
+    pub mean: [f64; 3],
+    pub swap_rb: bool,
+
+    // Apply softmax to the outputs, for models that give raw scores
+    pub softmax: bool,
+
+    pub min_confidence: f64,
+
+    // Added around motion regions before classifying them, in pixels
+    pub padding: i32,
+
+    // Only report these classes; all if empty
+    pub include: Vec<String>,
+
+    // Never report these classes
+    pub exclude: Vec<String>,
+
+    // Only save and notify about clips with one of the included classes in them
+    pub require: bool,
+
+    // Classify every n-th motion frame only, to keep up with the camera on a slow CPU
+    pub every_n_frames: u32,
+}
+
+
+impl Default for ClassifierConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: String::new(),
+            config: String::new(),
+            labels: String::new(),
+            input_width: 224,
+            input_height: 224,
+            scale: 1. / 255.,
+            mean: [0., 0., 0.],
+            swap_rb: true,
+            softmax: true,
+            min_confidence: 0.5,
+            padding: 16,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            require: false,
+            every_n_frames: 5,
+        }
+    }
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TrackingConfig {
+    pub enabled: bool,
+
+    // How motion is matched with the tracks of previous frames
+    pub method: TrackingMethod,
+
+    // Minimum overlap (intersection over union) of boxes matched by "iou", 0..1
+    pub min_iou: f64,
+
+    // Maximum distance between box centres matched by "centroid", in pixels
+    pub max_distance: f64,
+
+    // Frames an object may go unseen before its track ends
+    pub max_missed: u32,
+
+    // Smooth and predict positions with a Kalman filter
+    pub kalman: bool,
+}
+
+
+impl Default for TrackingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            method: TrackingMethod::Iou,
+            min_iou: 0.3,
+            max_distance: 80.,
+            max_missed: 10,
+            kalman: false,
+        }
+    }
+}
+
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all="lowercase")]
+pub enum TrackingMethod {
+    Iou,
+    Centroid,
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ReconnectConfig {
+    // Delay before the first reconnection attempt, in seconds; doubled after every failure
+    pub initial_delay: u64,
+
+    // Longest delay between reconnection attempts, in seconds
+    pub max_delay: u64,
+
+    // The source counts as lost when its timestamp hasn't advanced for this long, in seconds
+    pub stall_timeout: u64,
+}
+
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: 1,
+            max_delay: 60,
+            stall_timeout: 10,
+        }
+    }
+}
+
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ZoneConfig {
+    pub name: String,
+
+    #[serde(default="default_zone_kind")]
+    pub kind: ZoneKind,
+
+    // Polygon vertices, in pixels: [[x, y], [x, y], ...]
+    #[serde(deserialize_with="deserialize_points")]
+    pub points: Vec<Point>,
+
+    // Minimum area of detected motion inside this zone; the global sensitivity if omitted
+    #[serde(default)]
+    pub sensitivity: Option<i32>,
+}
+
+
+impl From<&ZoneConfig> for Zone {
+    fn from(config: &ZoneConfig) -> Self {
+        Zone {
+            name: config.name.clone(),
+            kind: config.kind,
+            polygon: config.points.clone(),
+            sensitivity: config.sensitivity,
+        }
+    }
+}
+
+
+fn default_zone_kind() -> ZoneKind { ZoneKind::Include }
+
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag="type", rename_all="lowercase")]
+pub enum DetectorConfig {
+    // Difference between consecutive frames
+    #[default]
+    Diff,
+
+    // Gaussian mixture background subtractor
+    Mog2 {
+        #[serde(default="default_history")]
+        history: i32,
+        #[serde(default="default_var_threshold")]
+        var_threshold: f64,
+        // Tell shadows apart from the foreground; they never count as motion
+        #[serde(default)]
+        detect_shadows: bool,
+        // -1 lets OpenCV pick the rate from history
+        #[serde(default="default_learning_rate")]
+        learning_rate: f64,
+    },
+
+    // K-nearest neighbours background subtractor
+    Knn {
+        #[serde(default="default_history")]
+        history: i32,
+        #[serde(default="default_dist2_threshold")]
+        dist2_threshold: f64,
+        // Tell shadows apart from the foreground; they never count as motion
+        #[serde(default)]
+        detect_shadows: bool,
+        #[serde(default="default_learning_rate")]
+        learning_rate: f64,
+    },
+
+    // Difference from a running average of previous frames
+    Average {
+        // Weight of the newest frame in the average, 0..1
+        #[serde(default="default_alpha")]
+        alpha: f64,
+    },
+}
+
+
+fn default_history() -> i32 { 500 }
+fn default_var_threshold() -> f64 { 16. }
+fn default_dist2_threshold() -> f64 { 400. }
+fn default_learning_rate() -> f64 { -1. }
+fn default_alpha() -> f64 { 0.05 }
+fn default_frame_width() -> i32 { 640 }
+fn default_frame_height() -> i32 { 480 }
+fn default_images_fps() -> f64 { 1. }
@@ -1,5 +1,8 @@
 pub mod types;
 pub mod conf;
+pub mod validate;
+pub mod summary;
 
 pub use types::*;
-pub use conf::*;
\ No newline at end of file
+pub use conf::*;
+pub use validate::*;
\ No newline at end of file
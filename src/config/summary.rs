@@ -0,0 +1,180 @@
+use std::fmt;
+
+use crate::camera::ZoneKind;
+use super::conf::*;
+use super::types::{fourcc_string, Color};
+
+
+/// The effective settings, as `check-config` prints them
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "headless: {}", yes_no(self.headless))?;
+        for camera in &self.cameras {
+            writeln!(f)?;
+            write!(f, "{}", camera)?;
+        }
+
+        writeln!(f)?;
+        let arming = &self.arming;
+        writeln!(f, "arming")?;
+        writeln!(f, "  armed on start: {}", yes_no(arming.armed_on_start))?;
+        writeln!(f, "  when disarmed: {}", match (arming.suppress_recordings, arming.suppress_notifications) {
+            (true, true) => "no clips, no notifications",
+            (true, false) => "no clips",
+            (false, true) => "no notifications",
+            (false, false) => "nothing changes",
+        })?;
+        for window in &arming.schedule {
+            let days: Vec<String> = window.days.iter().map(|day| day.to_string()).collect();
+            writeln!(f, "  armed {} {} - {}", days.join(" "), window.from.format("%H:%M"), window.to.format("%H:%M"))?;
+        }
+
+        writeln!(f)?;
+        let telegram = &self.telegram;
+        match telegram.enabled {
+            true => {
+                writeln!(f, "telegram: on, outbox {}", telegram.outbox)?;
+                if telegram.chats.is_empty() {
+                    writeln!(f, "  chat from CHAT_ID, admin")?;
+                }
+                for chat in &telegram.chats {
+                    let subscribed = if chat.subscribed { ", subscribed" } else { "" };
+                    writeln!(f, "  chat {}: {:?}{}", chat.id, chat.role, subscribed)?;
+                }
+                for user in &telegram.users {
+                    writeln!(f, "  user {}: {:?}", user.id, user.role)?;
+                }
+            }
+            false => writeln!(f, "telegram: off")?,
+        }
+
+        let notify = &self.notify;
+        if !notify.webhooks.is_empty() || !notify.email.is_empty() || !notify.commands.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "notify")?;
+        }
+        for webhook in &notify.webhooks {
+            writeln!(f, "  webhook {}{}, timeout {}s", webhook.url, disabled(webhook.enabled), webhook.timeout)?;
+        }
+        for email in &notify.email {
+            writeln!(f, "  email {} via {}:{}{}{}", email.to.join(", "), email.server, email.port,
+                disabled(email.enabled), if email.attach_clip { ", with clips" } else { "" })?;
+        }
+        for command in &notify.commands {
+            writeln!(f, "  command {} {}{}, timeout {}s", command.program, command.args.join(" "),
+                disabled(command.enabled), command.timeout)?;
+        }
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for DiffConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "camera {}", self.name)?;
+        writeln!(f, "  source: {}", match &self.source {
+            SourceConfig::Device { index, width, height } => format!("device {} at {}x{}", index, width, height),
+            SourceConfig::Url { url } => format!("stream {}", url),
+            SourceConfig::File { path } => format!("video file {}", path),
+            SourceConfig::Images { folder, fps } => format!("images in {} at {} fps", folder, fps),
+        })?;
+        writeln!(f, "  reconnect: after {}s, up to {}s, stalled after {}s",
+            self.reconnect.initial_delay, self.reconnect.max_delay, self.reconnect.stall_timeout)?;
+
+        writeln!(f, "  detector: {}", match &self.detector {
+            DetectorConfig::Diff => "frame difference".to_string(),
+            DetectorConfig::Mog2 { history, var_threshold, detect_shadows, learning_rate } => format!(
+                "MOG2, history {}, variance threshold {}, learning rate {}{}",
+                history, var_threshold, learning_rate, if *detect_shadows { ", shadows ignored" } else { "" }
+            ),
+            DetectorConfig::Knn { history, dist2_threshold, detect_shadows, learning_rate } => format!(
+                "KNN, history {}, distance threshold {}, learning rate {}{}",
+                history, dist2_threshold, learning_rate, if *detect_shadows { ", shadows ignored" } else { "" }
+            ),
+            DetectorConfig::Average { alpha } => format!("running average, alpha {}", alpha),
+        })?;
+        writeln!(f, "  motion: threshold {}, at least {} px, blur {} (sigma {}), dilate {} x{}",
+            self.threshold, self.sensitivity, self.blur_radius, self.blug_sigma,
+            self.dilate_radius, self.dilate_iterations)?;
+        for zone in &self.zones {
+            let kind = match zone.kind {
+                ZoneKind::Include => "include",
+                ZoneKind::Exclude => "exclude",
+            };
+            let points: Vec<String> = zone.points.iter().map(|p| format!("({}, {})", p.x, p.y)).collect();
+            write!(f, "  zone {} ({}): {}", zone.name, kind, points.join(" "))?;
+            match zone.sensitivity {
+                Some(sensitivity) => writeln!(f, ", at least {} px", sensitivity)?,
+                None => writeln!(f)?,
+            }
+        }
+
+        writeln!(f, "  clips: {}s to {}s, idle gap {}s, pre-roll {}s, post-roll {}s",
+            self.min_video_duration, self.max_video_duration, self.max_idle_gap,
+            self.pre_roll_duration, self.post_roll_duration)?;
+        let output = &self.output;
+        writeln!(f, "  output: {}/{}, {} at {} fps",
+            output.result_folder, output.result_filename_format, fourcc_string(output.fourcc), output.fps)?;
+        let retention = &output.retention;
+        let mut limits = Vec::new();
+        limits.extend(retention.max_age.map(|hours| format!("{} h", hours)));
+        limits.extend(retention.max_total_size.map(|mb| format!("{} MB", mb)));
+        limits.extend(retention.max_files.map(|files| format!("{} files", files)));
+        match limits.is_empty() {
+            true => writeln!(f, "  retention: keep everything")?,
+            false => writeln!(f, "  retention: {}, checked every {}s", limits.join(", "), retention.check_interval)?,
+        }
+        let overlay = &output.overlay;
+        match overlay.enabled {
+            true => writeln!(f, "  overlay: {:?} at ({}, {}), {}, scale {}{}{}{}",
+                overlay.timestamp_format, overlay.position.x, overlay.position.y,
+                Color::from_bgr(&overlay.color), overlay.font_scale,
+                if overlay.draw_boxes { ", boxes" } else { "" },
+                if overlay.draw_contours { ", contours" } else { "" },
+                if overlay.draw_boxes || overlay.draw_contours {
+                    format!(" in {}", Color::from_bgr(&overlay.motion_color))
+                } else {
+                    String::new()
+                },
+            )?,
+            false => writeln!(f, "  overlay: off")?,
+        }
+
+        let people = &self.people;
+        if people.enabled {
+            writeln!(f, "  people: confidence {}, every {} frames{}",
+                people.min_confidence, people.every_n_frames, if people.require { ", required" } else { "" })?;
+        }
+        let classifier = &self.classifier;
+        if classifier.enabled {
+            writeln!(f, "  classifier: {}, confidence {}, every {} frames{}",
+                classifier.model, classifier.min_confidence, classifier.every_n_frames,
+                if classifier.require { ", required" } else { "" })?;
+            if !classifier.include.is_empty() {
+                writeln!(f, "    include: {}", classifier.include.join(", "))?;
+            }
+            if !classifier.exclude.is_empty() {
+                writeln!(f, "    exclude: {}", classifier.exclude.join(", "))?;
+            }
+        }
+        let tracking = &self.tracking;
+        if tracking.enabled {
+            let method = match tracking.method {
+                TrackingMethod::Iou => format!("overlap of at least {}", tracking.min_iou),
+                TrackingMethod::Centroid => format!("centres within {} px", tracking.max_distance),
+            };
+            writeln!(f, "  tracking: {}, lost after {} frames{}",
+                method, tracking.max_missed, if tracking.kalman { ", Kalman filter" } else { "" })?;
+        }
+        Ok(())
+    }
+}
+
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+fn disabled(enabled: bool) -> &'static str {
+    if enabled { "" } else { " (disabled)" }
+}
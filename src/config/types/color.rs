@@ -1,3 +1,4 @@
+use std::fmt;
 use opencv::core::Scalar;
 use serde::{Deserialize, Deserializer, de::Error};
 use serde;
@@ -21,9 +22,35 @@ impl Into<Scalar> for Color {
 }
 
 
+impl Color {
+    /// In the BGR(A) channel order OpenCV draws in
+    pub fn to_bgr(&self) -> Scalar {
+        Scalar::new(
+            self.blue as f64, self.green as f64, self.red as f64, self.alpha as f64)
+    }
+
+    /// The colour of a scalar made by `to_bgr`
+    pub fn from_bgr(scalar: &Scalar) -> Self {
+        Color {
+            red: scalar.0[2] as u8,
+            green: scalar.0[1] as u8,
+            blue: scalar.0[0] as u8,
+            alpha: scalar.0[3] as u8,
+        }
+    }
+}
+
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02X}{:02X}{:02X}{:02X}", self.red, self.green, self.blue, self.alpha)
+    }
+}
+
+
 impl<'de> Deserialize<'de> for Color {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-        let src_text: &str = Deserialize::deserialize(deserializer)?;
+        let src_text: String = Deserialize::deserialize(deserializer)?;
         let re = Regex::new("#[0-9|A-F|a-f]{8}").map_err(D::Error::custom)?;
 
         if !re.is_match(&src_text) {
@@ -50,8 +77,17 @@ pub fn deserialize_color<'de, D>(deserializer: D) -> Result<Scalar, D::Error>
 }
 
 
+/// Like `deserialize_color`, for colours drawn on frames
+pub fn deserialize_bgr_color<'de, D>(deserializer: D) -> Result<Scalar, D::Error>
+    where D: Deserializer<'de> {
+    let src_color: Color = Deserialize::deserialize(deserializer)?;
+    Ok(src_color.to_bgr())
+}
+
+
 #[cfg(test)]
 mod tests {
+    use opencv::core::Scalar;
     use super::Color;
     use serde_json;
 
@@ -62,6 +98,15 @@ mod tests {
         assert_eq!(c, Color {red: 255, green: 0, blue: 16, alpha: 255} );
     }
 
+    #[test]
+    fn channel_order() {
+        let c: Color = serde_json::from_str("\"#10203040\"").unwrap();
+        assert_eq!(c.to_bgr(), Scalar::new(48., 32., 16., 64.));
+        assert_eq!(Color::from_bgr(&c.to_bgr()).to_string(), "#10203040");
+        let rgb: Scalar = c.into();
+        assert_eq!(rgb, Scalar::new(16., 32., 48., 64.));
+    }
+
     #[test]
     #[should_panic(expected="Incorrect color format")]
     fn deserialize_color_incorrect_format() {
@@ -3,13 +3,14 @@ use serde::{Deserialize, Deserializer, de::Error};
 use regex::Regex;
 
 
+/// Four character code of a codec, e.g. "mp4v", "XVID" or "H264"
 pub fn deserialize_fourcc<'de, D>(deserializer: D) -> Result<i32, D::Error>
     where D: Deserializer<'de> {
-    let src_string: &str = Deserialize::deserialize(deserializer)?;
-    let re = Regex::new("[A-Z]{4}").map_err(D::Error::custom)?;
+    let src_string: String = Deserialize::deserialize(deserializer)?;
+    let re = Regex::new("^[A-Za-z0-9 ]{4}$").map_err(D::Error::custom)?;
 
     if !re.is_match(&src_string) {
-        return Err(Error::custom("Incorrect fourcc format"))
+        return Err(Error::custom(format!("Incorrect fourcc format: {:?}", src_string)))
     }
 
     let mut chars = src_string.chars();
@@ -22,4 +23,36 @@ pub fn deserialize_fourcc<'de, D>(deserializer: D) -> Result<i32, D::Error>
             chars.next().unwrap()
         ).map_err(D::Error::custom)?
     )
-}
\ No newline at end of file
+}
+
+
+/// The four characters of a code made by `VideoWriter::fourcc`
+pub fn fourcc_string(fourcc: i32) -> String {
+    fourcc.to_le_bytes().iter().map(|&b| b as char).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use opencv::videoio::VideoWriter;
+    use serde::Deserialize;
+    use super::{deserialize_fourcc, fourcc_string};
+
+
+    #[derive(Deserialize)]
+    struct Output {
+        #[serde(deserialize_with="deserialize_fourcc")]
+        fourcc: i32,
+    }
+
+
+    #[test]
+    fn accepts_lowercase_and_digits() {
+        let output: Output = toml::from_str(r#"fourcc = "mp4v""#).unwrap();
+        assert_eq!(output.fourcc, VideoWriter::fourcc('m', 'p', '4', 'v').unwrap());
+        assert!(toml::from_str::<Output>(r#"fourcc = "H264""#).is_ok());
+        assert!(toml::from_str::<Output>(r#"fourcc = "mp4""#).is_err());
+        assert!(toml::from_str::<Output>(r#"fourcc = "mp4v1""#).is_err());
+        assert_eq!(fourcc_string(output.fourcc), "mp4v");
+    }
+}
@@ -3,10 +3,12 @@ pub mod point;
 pub mod size;
 pub mod fourcc;
 pub mod threshold;
+pub mod time;
 
 
 pub use color::*;
 pub use point::*;
 pub use size::*;
 pub use fourcc::*;
-pub use threshold::*;
\ No newline at end of file
+pub use threshold::*;
+pub use time::*;
\ No newline at end of file
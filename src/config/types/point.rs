@@ -6,4 +6,35 @@ pub fn deserialize_point<'de, D>(deserializer: D) -> Result<Point, D::Error>
     where D: Deserializer<'de> {
     let src_arr: [i32; 2] = Deserialize::deserialize(deserializer)?;
     Ok(Point::new(src_arr[0], src_arr[1]))
-}
\ No newline at end of file
+}
+
+pub fn deserialize_points<'de, D>(deserializer: D) -> Result<Vec<Point>, D::Error>
+    where D: Deserializer<'de> {
+    #[derive(Deserialize)]
+    struct Wrapper(#[serde(deserialize_with="deserialize_point")] Point);
+
+    let src_vec: Vec<Wrapper> = Deserialize::deserialize(deserializer)?;
+    Ok(src_vec.into_iter().map(|Wrapper(point)| point).collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::Point;
+    use serde::Deserialize;
+    use super::deserialize_points;
+
+
+    #[derive(Deserialize)]
+    struct Polygon {
+        #[serde(deserialize_with="deserialize_points")]
+        points: Vec<Point>,
+    }
+
+
+    #[test]
+    fn deserialize_points_list() {
+        let p: Polygon = toml::from_str("points = [[0, 0], [10, 0], [10, 20]]").unwrap();
+        assert_eq!(p.points, vec![Point::new(0, 0), Point::new(10, 0), Point::new(10, 20)]);
+    }
+}
@@ -0,0 +1,50 @@
+use chrono::{NaiveTime, Weekday};
+use serde::{Deserialize, Deserializer, de::Error};
+
+
+/// Parses "HH:MM"
+pub fn deserialize_time<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+    where D: Deserializer<'de> {
+    let src_text: String = Deserialize::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&src_text, "%H:%M")
+        .map_err(|_| Error::custom(format!("Incorrect time format: {}, expected HH:MM", src_text)))
+}
+
+
+/// Parses a list of weekday names: ["mon", "tue", ...]
+pub fn deserialize_weekdays<'de, D>(deserializer: D) -> Result<Vec<Weekday>, D::Error>
+    where D: Deserializer<'de> {
+    let src_vec: Vec<String> = Deserialize::deserialize(deserializer)?;
+    src_vec.into_iter()
+        .map(|day| day.parse::<Weekday>()
+            .map_err(|_| Error::custom(format!("Incorrect weekday: {}", day))))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveTime, Weekday};
+    use serde::Deserialize;
+    use super::{deserialize_time, deserialize_weekdays};
+
+
+    #[derive(Deserialize)]
+    struct Window {
+        #[serde(deserialize_with="deserialize_time")]
+        start: NaiveTime,
+        #[serde(deserialize_with="deserialize_weekdays")]
+        days: Vec<Weekday>,
+    }
+
+
+    #[test]
+    fn parses_escaped_strings() {
+        let window: Window = toml::from_str(r#"
+            start = "22\u003A30"
+            days = ["mon", "s\u0061t"]
+        "#).unwrap();
+        assert_eq!(window.start, NaiveTime::from_hms_opt(22, 30, 0).unwrap());
+        assert_eq!(window.days, vec![Weekday::Mon, Weekday::Sat]);
+    }
+}
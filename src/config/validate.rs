@@ -0,0 +1,298 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use anyhow::{Error, Result};
+use log::*;
+
+use super::conf::*;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    // The config can't be used
+    Error,
+    // The config works, but probably not as intended
+    Warning,
+}
+
+
+/// Something wrong with a config value
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub severity: Severity,
+    // e.g. "cameras[0].blur_radius"
+    pub path: String,
+    pub message: String,
+}
+
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", severity, self.path, self.message)
+    }
+}
+
+
+/// Problems found in a config
+#[derive(Default, Debug)]
+pub struct Validation {
+    pub problems: Vec<Problem>,
+}
+
+
+impl Validation {
+    fn error(&mut self, path: &str, message: impl Into<String>) {
+        self.problems.push(Problem { severity: Severity::Error, path: path.to_string(), message: message.into() });
+    }
+
+    fn warning(&mut self, path: &str, message: impl Into<String>) {
+        self.problems.push(Problem { severity: Severity::Warning, path: path.to_string(), message: message.into() });
+    }
+
+    fn check(&mut self, ok: bool, path: &str, message: &str) {
+        if !ok { self.error(path, message) }
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        self.problems.iter().any(|p| p.severity == Severity::Error)
+    }
+
+    /// Logs the warnings; fails with all the errors if there are any
+    pub fn into_result(self) -> Result<()> {
+        for problem in self.problems.iter().filter(|p| p.severity == Severity::Warning) {
+            warn!("{}", problem);
+        }
+        if !self.is_fatal() {
+            return Ok(())
+        }
+        let errors: Vec<String> = self.problems.iter()
+            .filter(|p| p.severity == Severity::Error)
+            .map(|p| p.to_string())
+            .collect();
+        Err(Error::msg(format!("Invalid configuration:\n{}", errors.join("\n"))))
+    }
+}
+
+
+impl Config {
+    pub fn validate(&self) -> Validation {
+        let mut v = Validation::default();
+
+        let mut names = HashSet::new();
+        let mut outputs = HashSet::new();
+        for (i, camera) in self.cameras.iter().enumerate() {
+            let path = format!("cameras[{}]", i);
+            if !names.insert(camera.name.as_str()) {
+                v.error(&format!("{}.name", path), format!("duplicate camera name {:?}", camera.name));
+            }
+            let output = &camera.output;
+            if !outputs.insert((output.result_folder.as_str(), output.result_filename_format.as_str())) {
+                v.error(&format!("{}.output.result_filename_format", path), format!(
+                    "{:?} in {:?} is used by another camera; put {{camera}} in it", output.result_filename_format, output.result_folder
+                ));
+            }
+            camera.validate_into(&path, &mut v);
+        }
+
+        for (i, window) in self.arming.schedule.iter().enumerate() {
+            let path = format!("arming.schedule[{}]", i);
+            if window.days.is_empty() {
+                v.warning(&format!("{}.days", path), "no days, the window never applies");
+            }
+            if window.from == window.to {
+                v.warning(&format!("{}.to", path), "window is empty");
+            }
+        }
+
+        for (i, webhook) in self.notify.webhooks.iter().enumerate() {
+            let path = format!("notify.webhooks[{}]", i);
+            v.check(webhook.url.starts_with("http://") || webhook.url.starts_with("https://"),
+                &format!("{}.url", path), "must be an http:// or https:// URL");
+        }
+        for (i, email) in self.notify.email.iter().enumerate() {
+            let path = format!("notify.email[{}]", i);
+            v.check(!email.server.is_empty(), &format!("{}.server", path), "must not be empty");
+            v.check(!email.to.is_empty(), &format!("{}.to", path), "needs at least one recipient");
+        }
+        for (i, command) in self.notify.commands.iter().enumerate() {
+            let path = format!("notify.commands[{}]", i);
+            v.check(!command.program.is_empty(), &format!("{}.program", path), "must not be empty");
+        }
+
+        v
+    }
+}
+
+
+impl DiffConfig {
+    /// Validates a camera config found at `path`, e.g. "cameras[0]"
+    fn validate_into(&self, path: &str, v: &mut Validation) {
+        let field = |name: &str| format!("{}.{}", path, name);
+
+        v.check(!self.name.is_empty(), &field("name"), "must not be empty");
+        v.check(self.blur_radius > 0 && self.blur_radius % 2 == 1, &field("blur_radius"), "must be a positive odd number");
+        v.check(self.blug_sigma >= 0., &field("blug_sigma"), "must not be negative");
+        v.check(self.dilate_radius > 0, &field("dilate_radius"), "must be positive");
+        v.check(self.dilate_iterations >= 0, &field("dilate_iterations"), "must not be negative");
+        v.check(self.sensitivity >= 0, &field("sensitivity"), "must not be negative");
+        v.check((0..=255).contains(&self.threshold), &field("threshold"), "must be between 0 and 255");
+
+        if self.min_video_duration > self.max_video_duration {
+            v.error(&field("min_video_duration"), format!(
+                "{}s is longer than max_video_duration ({}s)", self.min_video_duration, self.max_video_duration
+            ));
+        }
+
+        let output = &self.output;
+        v.check(output.fps > 0., &field("output.fps"), "must be positive");
+        v.check(!output.result_filename_format.is_empty(), &field("output.result_filename_format"), "must not be empty");
+        v.check(!output.result_folder.is_empty(), &field("output.result_folder"), "must not be empty");
+        v.check(output.retention.check_interval > 0, &field("output.retention.check_interval"), "must be positive");
+        if output.overlay.enabled {
+            v.check(output.overlay.font_scale > 0., &field("output.overlay.font_scale"), "must be positive");
+            v.check(output.overlay.thickness > 0, &field("output.overlay.thickness"), "must be positive");
+        }
+
+        match &self.source {
+            SourceConfig::Device { index, width, height } => {
+                v.check(*index >= 0, &field("source.index"), "must not be negative");
+                v.check(*width > 0, &field("source.width"), "must be positive");
+                v.check(*height > 0, &field("source.height"), "must be positive");
+            }
+            SourceConfig::Url { url } => {
+                v.check(!url.is_empty(), &field("source.url"), "must not be empty");
+            }
+            SourceConfig::File { path: file } => {
+                if !Path::new(file).exists() {
+                    v.warning(&field("source.path"), format!("{} does not exist", file));
+                }
+            }
+            SourceConfig::Images { folder, fps } => {
+                if !Path::new(folder).is_dir() {
+                    v.warning(&field("source.folder"), format!("{} is not a directory", folder));
+                }
+                v.check(*fps > 0., &field("source.fps"), "must be positive");
+            }
+        }
+
+        v.check(self.reconnect.initial_delay > 0, &field("reconnect.initial_delay"), "must be positive");
+        v.check(self.reconnect.max_delay >= self.reconnect.initial_delay, &field("reconnect.max_delay"),
+            "must not be shorter than initial_delay");
+        v.check(self.reconnect.stall_timeout > 0, &field("reconnect.stall_timeout"), "must be positive");
+
+        if self.people.enabled {
+            v.check(self.people.padding >= 0, &field("people.padding"), "must not be negative");
+            v.check(self.people.every_n_frames > 0, &field("people.every_n_frames"), "must be positive");
+        } else if self.people.require {
+            v.error(&field("people.require"), "requires people.enabled");
+        }
+
+        let classifier = &self.classifier;
+        if classifier.enabled {
+            if !Path::new(&classifier.model).is_file() {
+                v.error(&field("classifier.model"), format!("{:?} is not a file", classifier.model));
+            }
+            if !classifier.config.is_empty() && !Path::new(&classifier.config).is_file() {
+                v.error(&field("classifier.config"), format!("{:?} is not a file", classifier.config));
+            }
+            if !Path::new(&classifier.labels).is_file() {
+                v.error(&field("classifier.labels"), format!("{:?} is not a file", classifier.labels));
+            }
+            v.check(classifier.input_width > 0, &field("classifier.input_width"), "must be positive");
+            v.check(classifier.input_height > 0, &field("classifier.input_height"), "must be positive");
+            v.check((0. ..=1.).contains(&classifier.min_confidence), &field("classifier.min_confidence"),
+                "must be between 0 and 1");
+            v.check(classifier.padding >= 0, &field("classifier.padding"), "must not be negative");
+            v.check(classifier.every_n_frames > 0, &field("classifier.every_n_frames"), "must be positive");
+            v.check(!classifier.require || !classifier.include.is_empty(), &field("classifier.require"),
+                "needs the classes to require in classifier.include");
+            for label in classifier.include.iter().filter(|label| classifier.exclude.contains(label)) {
+                v.warning(&field("classifier.exclude"), format!("{:?} is also included, it will never be reported", label));
+            }
+        } else if classifier.require {
+            v.error(&field("classifier.require"), "requires classifier.enabled");
+        }
+
+        if self.tracking.enabled {
+            match self.tracking.method {
+                TrackingMethod::Iou => v.check(self.tracking.min_iou > 0. && self.tracking.min_iou <= 1.,
+                    &field("tracking.min_iou"), "must be in (0, 1]"),
+                TrackingMethod::Centroid => v.check(self.tracking.max_distance > 0.,
+                    &field("tracking.max_distance"), "must be positive"),
+            }
+            v.check(self.tracking.max_missed > 0, &field("tracking.max_missed"), "must be positive");
+        }
+
+        match &self.detector {
+            DetectorConfig::Diff => {}
+            DetectorConfig::Mog2 { history, var_threshold, learning_rate, .. } => {
+                v.check(*history > 0, &field("detector.history"), "must be positive");
+                v.check(*var_threshold > 0., &field("detector.var_threshold"), "must be positive");
+                v.check(*learning_rate <= 1., &field("detector.learning_rate"), "must be at most 1");
+            }
+            DetectorConfig::Knn { history, dist2_threshold, learning_rate, .. } => {
+                v.check(*history > 0, &field("detector.history"), "must be positive");
+                v.check(*dist2_threshold > 0., &field("detector.dist2_threshold"), "must be positive");
+                v.check(*learning_rate <= 1., &field("detector.learning_rate"), "must be at most 1");
+            }
+            DetectorConfig::Average { alpha } => {
+                v.check(*alpha > 0. && *alpha <= 1., &field("detector.alpha"), "must be in (0, 1]");
+            }
+        }
+
+        let mut zone_names = HashSet::new();
+        for (i, zone) in self.zones.iter().enumerate() {
+            let zone_field = |name: &str| format!("{}.zones[{}].{}", path, i, name);
+            v.check(!zone.name.is_empty(), &zone_field("name"), "must not be empty");
+            if !zone_names.insert(zone.name.as_str()) {
+                v.warning(&zone_field("name"), format!("duplicate zone name {:?}", zone.name));
+            }
+            v.check(zone.points.len() >= 3, &zone_field("points"), "a polygon needs at least 3 points");
+            if let Some(sensitivity) = zone.sensitivity {
+                v.check(sensitivity >= 0, &zone_field("sensitivity"), "must not be negative");
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, DiffConfig};
+    use super::Severity;
+
+
+    #[test]
+    fn defaults_are_valid() {
+        let config = Config { cameras: vec![DiffConfig::default()], ..Config::default() };
+        assert!(!config.validate().is_fatal());
+    }
+
+    #[test]
+    fn reports_every_problem_with_its_path() {
+        let camera = DiffConfig {
+            blur_radius: 4,
+            min_video_duration: 20,
+            max_video_duration: 10,
+            ..DiffConfig::default()
+        };
+        let config = Config { cameras: vec![DiffConfig::default(), camera], ..Config::default() };
+        let validation = config.validate();
+
+        let errors: Vec<&str> = validation.problems.iter()
+            .filter(|p| p.severity == Severity::Error)
+            .map(|p| p.path.as_str())
+            .collect();
+        assert_eq!(errors, vec![
+            "cameras[1].name",
+            "cameras[1].output.result_filename_format",
+            "cameras[1].blur_radius",
+            "cameras[1].min_video_duration",
+        ]);
+        assert!(validation.into_result().is_err());
+    }
+}
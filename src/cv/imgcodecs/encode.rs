@@ -0,0 +1,12 @@
+use opencv::core::{ToInputArray, Vector};
+use opencv::imgcodecs::{imencode, IMWRITE_JPEG_QUALITY};
+use opencv::Result;
+
+
+/// Encodes an image as JPEG with the given quality (0..100)
+pub fn encode_jpeg(src: &dyn ToInputArray, quality: i32) -> Result<Vec<u8>> {
+    let mut buf = Vector::<u8>::new();
+    let params = Vector::<i32>::from_iter([IMWRITE_JPEG_QUALITY, quality]);
+    imencode(".jpg", src, &mut buf, &params)?;
+    Ok(buf.to_vec())
+}
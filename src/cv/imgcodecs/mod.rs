@@ -0,0 +1,4 @@
+pub mod encode;
+
+
+pub use encode::*;
\ No newline at end of file
@@ -1,6 +1,6 @@
 use opencv::prelude::*;
-use opencv::core::{no_array, Point, Scalar, ToInputArray, ToInputOutputArray};
-use opencv::imgproc::{draw_contours, LINE_AA};
+use opencv::core::{no_array, Point, Rect, Scalar, ToInputArray, ToInputOutputArray};
+use opencv::imgproc::{draw_contours, rectangle, LINE_AA};
 use opencv::Result;
 
 
@@ -9,6 +9,8 @@ pub struct DrawContours {
     color: Scalar,
     thickness: i32,
     line_type: i32,
+    // Which contour to draw; -1 draws all of them
+    index: i32,
 }
 
 
@@ -18,6 +20,7 @@ impl Default for DrawContours {
             color: Scalar::new(0 as f64, 255 as f64, 0 as f64, 255 as f64),
             thickness: 1,
             line_type: LINE_AA,
+            index: 0,
         }
     }
 }
@@ -25,11 +28,18 @@ impl Default for DrawContours {
 
 impl DrawContours {
     pub fn new(color: Scalar, thickness: i32, line_type: i32) -> Self {
-        Self { color, thickness, line_type }
+        Self { color, thickness, line_type, index: 0 }
+    }
+
+    pub fn with_index(self, index: i32) -> Self {
+        Self {
+            index,
+            ..self
+        }
     }
 
     pub fn dest(&self, dest: &mut dyn ToInputOutputArray, contours: &dyn ToInputArray) -> Result<()> {
-        let idx: i32 = 0;
+        let idx: i32 = self.index;
         let hierarchy = no_array();
         let max_result = 2;
         let zero_offset = Point::new(0, 0);
@@ -57,3 +67,42 @@ impl DrawContours {
         Ok(result)
     }
 }
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct DrawRectangles {
+    color: Scalar,
+    thickness: i32,
+    line_type: i32,
+}
+
+
+impl Default for DrawRectangles {
+    fn default() -> Self {
+        Self {
+            color: Scalar::new(0 as f64, 255 as f64, 0 as f64, 255 as f64),
+            thickness: 1,
+            line_type: LINE_AA,
+        }
+    }
+}
+
+
+impl DrawRectangles {
+    pub fn new(color: Scalar, thickness: i32, line_type: i32) -> Self {
+        Self { color, thickness, line_type }
+    }
+
+    pub fn dest(&self, dest: &mut dyn ToInputOutputArray, rects: &[Rect]) -> Result<()> {
+        for rect in rects {
+            rectangle(dest, *rect, self.color, self.thickness, self.line_type, 0)?;
+        }
+        Ok(())
+    }
+
+    pub fn prep(&self, src: &Mat, rects: &[Rect]) -> Result<Mat> {
+        let mut result = src.clone();
+        self.dest(&mut result, rects)?;
+        Ok(result)
+    }
+}
@@ -6,6 +6,7 @@ pub mod cvt_color;
 pub mod treshold;
 pub mod draw;
 pub mod find;
+pub mod text;
 
 
 pub use traits::*;
@@ -15,4 +16,5 @@ pub use conv::*;
 pub use cvt_color::*;
 pub use treshold::*;
 pub use draw::*;
-pub use find::*;
\ No newline at end of file
+pub use find::*;
+pub use text::*;
\ No newline at end of file
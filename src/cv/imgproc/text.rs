@@ -0,0 +1,56 @@
+use opencv::prelude::*;
+use opencv::core::{Point, Scalar, ToInputOutputArray};
+use opencv::imgproc::{put_text, FONT_HERSHEY_SIMPLEX, LINE_AA};
+use opencv::Result;
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct PutText {
+    font_face: i32,
+    font_scale: f64,
+    color: Scalar,
+    thickness: i32,
+    line_type: i32,
+}
+
+
+impl Default for PutText {
+    fn default() -> Self {
+        Self {
+            font_face: FONT_HERSHEY_SIMPLEX,
+            font_scale: 0.5,
+            color: Scalar::new(255 as f64, 255 as f64, 255 as f64, 255 as f64),
+            thickness: 1,
+            line_type: LINE_AA,
+        }
+    }
+}
+
+
+impl PutText {
+    pub fn new(font_face: i32, font_scale: f64, color: Scalar, thickness: i32, line_type: i32) -> Self {
+        Self { font_face, font_scale, color, thickness, line_type }
+    }
+
+    /// Draws `text` with its bottom-left corner at `origin`
+    pub fn dest(&self, dest: &mut dyn ToInputOutputArray, text: &str, origin: Point) -> Result<()> {
+        put_text(
+            dest,
+            text,
+            origin,
+            self.font_face,
+            self.font_scale,
+            self.color,
+            self.thickness,
+            self.line_type,
+            false
+        )?;
+        Ok(())
+    }
+
+    pub fn prep(&self, src: &Mat, text: &str, origin: Point) -> Result<Mat> {
+        let mut result = src.clone();
+        self.dest(&mut result, text, origin)?;
+        Ok(result)
+    }
+}
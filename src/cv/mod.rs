@@ -1,5 +1,7 @@
 pub mod imgproc;
 pub mod videoio;
+pub mod imgcodecs;
 
 pub use imgproc::*;
-pub use videoio::*;
\ No newline at end of file
+pub use videoio::*;
+pub use imgcodecs::*;
\ No newline at end of file
@@ -1,4 +1,6 @@
 pub mod writer;
+pub mod source;
 
 
-pub use writer::*;
\ No newline at end of file
+pub use writer::*;
+pub use source::*;
\ No newline at end of file
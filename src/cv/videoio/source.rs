@@ -0,0 +1,217 @@
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+use anyhow::{Error, Result};
+use opencv::prelude::*;
+use opencv::imgcodecs::{imread, IMREAD_COLOR};
+use opencv::videoio::{
+    VideoCapture, CAP_ANY, CAP_PROP_FPS, CAP_PROP_FRAME_HEIGHT, CAP_PROP_FRAME_WIDTH,
+    CAP_PROP_POS_MSEC,
+};
+use log::*;
+
+
+/// A source of frames for the motion detection pipeline
+pub trait FrameSource {
+    /// Reads the next frame
+    ///
+    /// Returns `Ok(None)` when a finite source (a video file or an image directory) is exhausted.
+    fn read(&mut self) -> Result<Option<Mat>>;
+
+    /// Native framerate of the source, if it is known
+    fn fps(&self) -> Option<f64> { None }
+
+    /// Stream timestamp of the last frame read, in milliseconds, if the source provides one
+    fn timestamp(&self) -> Option<f64> { None }
+}
+
+
+/// Captures frames from a local device (V4L index)
+pub struct DeviceSource {
+    capture: VideoCapture,
+}
+
+
+impl DeviceSource {
+    pub fn new(index: i32, width: i32, height: i32) -> Result<Self> {
+        info!("Opening capture device {}", index);
+        let mut capture = VideoCapture::new(index, CAP_ANY)?;
+        if !capture.is_opened()? {
+            return Err(Error::msg(format!("Cannot open capture device {}", index)))
+        }
+        capture.set(CAP_PROP_FRAME_WIDTH, width as f64)?;
+        capture.set(CAP_PROP_FRAME_HEIGHT, height as f64)?;
+        Ok(Self { capture })
+    }
+}
+
+
+impl FrameSource for DeviceSource {
+    fn read(&mut self) -> Result<Option<Mat>> {
+        read_live(&mut self.capture).map(Some)
+    }
+
+    fn timestamp(&self) -> Option<f64> {
+        live_timestamp(&self.capture)
+    }
+}
+
+
+/// Captures frames from a network stream (RTSP, HTTP MJPEG, anything ffmpeg can open)
+pub struct UrlSource {
+    capture: VideoCapture,
+}
+
+
+impl UrlSource {
+    pub fn new(url: &str) -> Result<Self> {
+        info!("Opening stream {}", url);
+        let capture = VideoCapture::from_file(url, CAP_ANY)?;
+        if !capture.is_opened()? {
+            return Err(Error::msg(format!("Cannot open stream: {}", url)))
+        }
+        Ok(Self { capture })
+    }
+}
+
+
+impl FrameSource for UrlSource {
+    fn read(&mut self) -> Result<Option<Mat>> {
+        read_live(&mut self.capture).map(Some)
+    }
+
+    fn timestamp(&self) -> Option<f64> {
+        live_timestamp(&self.capture)
+    }
+}
+
+
+/// Reads frames from a video file
+pub struct FileSource {
+    capture: VideoCapture,
+    fps: Option<f64>,
+}
+
+
+impl FileSource {
+    pub fn new(path: &str) -> Result<Self> {
+        info!("Opening video file {}", path);
+        let capture = VideoCapture::from_file(path, CAP_ANY)?;
+        if !capture.is_opened()? {
+            return Err(Error::msg(format!("Cannot open video file: {}", path)))
+        }
+        let fps = capture.get(CAP_PROP_FPS)?;
+        Ok(Self {
+            capture,
+            fps: if fps > 0. { Some(fps) } else { None },
+        })
+    }
+}
+
+
+impl FrameSource for FileSource {
+    fn read(&mut self) -> Result<Option<Mat>> {
+        let mut frame = Mat::default();
+        if !self.capture.read(&mut frame)? || frame.size()?.width == 0 {
+            return Ok(None)
+        }
+        Ok(Some(frame))
+    }
+
+    fn fps(&self) -> Option<f64> {
+        self.fps
+    }
+}
+
+
+/// Reads still images from a directory in filename order
+pub struct ImageDirSource {
+    files: Vec<PathBuf>,
+    position: usize,
+    fps: f64,
+}
+
+
+impl ImageDirSource {
+    pub fn new(folder: &str, fps: f64) -> Result<Self> {
+        let mut files = read_dir(folder)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file() && is_image(path))
+            .collect::<Vec<PathBuf>>();
+        files.sort();
+
+        info!("Found {} images in {}", files.len(), folder);
+
+        Ok(Self { files, position: 0, fps })
+    }
+}
+
+
+impl FrameSource for ImageDirSource {
+    fn read(&mut self) -> Result<Option<Mat>> {
+        let path = match self.files.get(self.position) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        self.position += 1;
+
+        let path_str = path.to_str().ok_or(Error::msg("Improper filename"))?;
+        let frame = imread(path_str, IMREAD_COLOR)?;
+        if frame.size()?.width == 0 {
+            return Err(Error::msg(format!("Cannot read image: {}", path_str)))
+        }
+        Ok(Some(frame))
+    }
+
+    fn fps(&self) -> Option<f64> {
+        Some(self.fps)
+    }
+}
+
+
+fn read_live(capture: &mut VideoCapture) -> Result<Mat> {
+    let mut frame = Mat::default();
+    if !capture.read(&mut frame)? || frame.size()?.width == 0 {
+        return Err(Error::msg("Failed to read a frame"))
+    }
+    Ok(frame)
+}
+
+
+fn live_timestamp(capture: &VideoCapture) -> Option<f64> {
+    capture.get(CAP_PROP_POS_MSEC).ok().filter(|ms| *ms > 0.)
+}
+
+
+fn is_image(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => matches!(
+            ext.to_lowercase().as_str(), "jpg" | "jpeg" | "png" | "bmp" | "tif" | "tiff"
+        ),
+        None => false,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use super::ImageDirSource;
+
+
+    #[test]
+    fn image_dir_lists_images_in_filename_order() {
+        let folder = std::env::temp_dir().join(format!("image-dir-source-test-{}", std::process::id()));
+        remove_dir_all(&folder).ok();
+        create_dir_all(folder.join("nested.png")).unwrap();
+        for name in ["b.png", "a.JPG", "c.txt", "d"] {
+            write(folder.join(name), b"").unwrap();
+        }
+
+        let source = ImageDirSource::new(folder.to_str().unwrap(), 5.).unwrap();
+        let names: Vec<String> = source.files.iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["a.JPG", "b.png"]);
+        remove_dir_all(&folder).ok();
+    }
+}
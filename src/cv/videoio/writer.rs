@@ -1,4 +1,4 @@
-use std::fs::create_dir_all;
+use std::fs::{create_dir_all, remove_file, rename};
 use std::path::Path;
 use std::time::Duration;
 use chrono::prelude::*;
@@ -10,6 +10,8 @@ use opencv::imgproc::{InterpolationFlags, resize};
 use opencv::videoio::VideoWriter;
 use log::*;
 
+use crate::config::fourcc_string;
+
 
 pub trait VideoFileWriterTrait {
     //! Writes a video file to `path`
@@ -79,6 +81,20 @@ pub struct VideoFileWriter {
 }
 
 
+/// A video file that is being written frame by frame
+///
+/// The file is finalised with `finish`, or released and removed with `discard`.
+pub struct VideoFileStream {
+    writer: VideoWriter,
+    path: String,
+    // Where `finish` moves the file, if it is written under an in-progress name
+    final_path: Option<String>,
+    frame_size: Size,
+    resize_interpolation: Option<i32>,
+    frames: usize,
+}
+
+
 pub struct VideoFileDirWriter {
     writer: VideoFileWriter,
     filename_format: String,
@@ -110,17 +126,49 @@ impl VideoFileWriter {
         }
     }
 
-    fn resize_frame(&self, frame: &Mat, size: Size, interpolation: i32) -> Result<Mat> {
-        let mut resized_frame = Mat::default();
-        resize(
-            &frame,
-            &mut resized_frame,
-            size,
-            0_f64,
-            0_f64,
-            interpolation
-        )?;
-        Ok(resized_frame)
+    /// Opens a video file for streamed writing
+    ///
+    /// The first frame is only used to derive the frame size and is not written.
+    /// Streamed files require a static FPS, since the number of frames is not known in advance.
+    pub fn open(&self, path: &str, first_frame: &Mat) -> Result<VideoFileStream> {
+        let fps = match self.fps {
+            FPSConfig::Static(fps) => { fps }
+            FPSConfig::Derived(_) => {
+                return Err(Error::msg("Cannot stream video file: derived FPS requires all frames"))
+            }
+        };
+        self.create_stream(path, fps, first_frame)
+    }
+
+    fn create_stream(&self, path: &str, fps: f64, first_frame: &Mat) -> Result<VideoFileStream> {
+        let frame_size = match self.frame_size {
+            FrameSizeConfig::Static(size) | FrameSizeConfig::Resize(size, _) => { size }
+            FrameSizeConfig::DeriveResize(_) | FrameSizeConfig::Derive => {
+                first_frame.size()?
+            }
+        };
+        let resize_interpolation = match self.frame_size {
+            FrameSizeConfig::Static(_) | FrameSizeConfig::Derive => { None }
+            FrameSizeConfig::Resize(_, i) | FrameSizeConfig::DeriveResize(i) => { Some(i) }
+        };
+
+        let writer = self.create_writer(path, fps, frame_size, self.is_color)?;
+        // An unsupported codec or container doesn't fail here, it just writes nothing
+        if !writer.is_opened()? {
+            return Err(Error::msg(format!(
+                "Cannot open {} for writing: fourcc {:?} or the container is not supported",
+                path, fourcc_string(self.fourcc)
+            )))
+        }
+
+        Ok(VideoFileStream {
+            writer,
+            path: path.to_string(),
+            final_path: None,
+            frame_size,
+            resize_interpolation,
+            frames: 0,
+        })
     }
 
     fn create_writer(&self, filename: &str, fps: f64, frame_size: Size, is_color: bool) -> opencv::Result<VideoWriter> {
@@ -160,31 +208,13 @@ impl VideoFileWriterTrait for VideoFileWriter {
             FPSConfig::Derived(duration) => { content.len() as f64 / duration.as_secs() as f64}
         };
 
-        let frame_size = match self.frame_size {
-            FrameSizeConfig::Static(size) | FrameSizeConfig::Resize(size, _) => { size }
-            FrameSizeConfig::DeriveResize(_) | FrameSizeConfig::Derive => {
-                content.first().unwrap().size()?
-            }
-        };
-        let resize_interpolation = match self.frame_size {
-            FrameSizeConfig::Static(_) | FrameSizeConfig::Derive => { None }
-            FrameSizeConfig::Resize(_, i) | FrameSizeConfig::DeriveResize(i) => { Some(i) }
-        };
-
-        let mut writer = self.create_writer(path, fps, frame_size, self.is_color)?;
-
+        let mut stream = self.create_stream(path, fps, content.first().unwrap())?;
 
         for frame in content {
-            match resize_interpolation {
-                Some(i) => {
-                    let new_frame = self.resize_frame(frame, frame_size, i)?;
-                    writer.write(&new_frame)?;
-                }
-                None => { writer.write(frame)?; }
-            }
+            stream.write(frame)?;
         };
 
-        writer.release()?;
+        stream.finish()?;
 
         Ok(())
     }
@@ -204,6 +234,49 @@ impl Default for VideoFileWriter {
 }
 
 
+impl VideoFileStream {
+    pub fn write(&mut self, frame: &Mat) -> Result<()> {
+        match self.resize_interpolation {
+            Some(i) => {
+                let new_frame = resize_frame(frame, self.frame_size, i)?;
+                self.writer.write(&new_frame)?;
+            }
+            None => { self.writer.write(frame)?; }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Number of frames written so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Finalises the file and returns its path
+    pub fn finish(mut self) -> Result<String> {
+        self.writer.release()?;
+        match self.final_path {
+            Some(final_path) => {
+                rename(&self.path, &final_path)?;
+                Ok(final_path)
+            }
+            None => Ok(self.path),
+        }
+    }
+
+    /// Releases and removes the file
+    pub fn discard(mut self) -> Result<()> {
+        self.writer.release()?;
+        remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+
 impl VideoFileDirWriter {
     pub fn new(writer: VideoFileWriter, filename_format: &str, folder: &str) -> Self {
         Self {
@@ -212,11 +285,20 @@ impl VideoFileDirWriter {
             folder: folder.to_string()
         }
     }
-}
 
+    /// Opens a new file in the folder for streamed writing. See `VideoFileWriter::open`
+    ///
+    /// The file gets its name only when finished; until then it is hidden, see `is_in_progress`.
+    pub fn open(&self, first_frame: &Mat) -> Result<VideoFileStream> {
+        let path = self.next_path()?;
+        let writing_path = in_progress_path(&path);
+        debug!("Streaming frames to: {}", &writing_path);
+        let mut stream = self.writer.open(&writing_path, first_frame)?;
+        stream.final_path = Some(path);
+        Ok(stream)
+    }
 
-impl VideoSelectedFileWriterTrait for VideoFileDirWriter {
-    fn save(&self, content: &Vec<Mat>) -> Result<String> {
+    fn next_path(&self) -> Result<String> {
         let folder_path = Path::new(&self.folder);
         create_dir_all(folder_path)?;
 
@@ -226,10 +308,47 @@ impl VideoSelectedFileWriterTrait for VideoFileDirWriter {
         let joined = folder_path.join(fnp);
         let joined_str = joined.to_str().ok_or(Error::msg("Improper filename"))?;
 
-        debug!("Saving {} frames to: {}", content.len(), &joined_str);
+        Ok(joined_str.to_string())
+    }
+}
 
-        self.writer.save(&joined_str, content)?;
 
-        Ok(joined_str.to_string())
+impl VideoSelectedFileWriterTrait for VideoFileDirWriter {
+    fn save(&self, content: &Vec<Mat>) -> Result<String> {
+        let path = self.next_path()?;
+
+        debug!("Saving {} frames to: {}", content.len(), &path);
+
+        self.writer.save(&path, content)?;
+
+        Ok(path)
     }
 }
+
+
+/// Whether a file in an output folder is a clip still being written
+pub fn is_in_progress(path: &Path) -> bool {
+    path.file_name().map_or(false, |name| name.to_string_lossy().starts_with('.'))
+}
+
+
+// Same folder and extension, so the container is the same; the leading dot hides it
+fn in_progress_path(path: &str) -> String {
+    let path = Path::new(path);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}", name)).to_string_lossy().to_string()
+}
+
+
+fn resize_frame(frame: &Mat, size: Size, interpolation: i32) -> Result<Mat> {
+    let mut resized_frame = Mat::default();
+    resize(
+        &frame,
+        &mut resized_frame,
+        size,
+        0_f64,
+        0_f64,
+        interpolation
+    )?;
+    Ok(resized_frame)
+}
@@ -0,0 +1,21 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+
+
+/// Writes a file through a temporary file renamed over it, so a crash never leaves it half-written
+pub fn write_atomically<F>(path: &Path, write: F) -> Result<()> where F: FnOnce(&mut File) -> Result<()> {
+    let tmp_path = tmp_path(path);
+    let mut file = File::create(&tmp_path)?;
+    write(&mut file)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
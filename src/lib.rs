@@ -4,6 +4,15 @@ pub mod config;
 
 pub mod signals;
 pub mod cam;
+#[cfg(feature="telegram")]
 pub mod telegram;
 pub mod broadcast;
+pub mod retention;
+pub mod schedule;
+pub mod outbox;
+pub mod fs;
+pub mod notify;
+pub mod reload;
+pub mod replay;
+pub mod supervisor;
 
@@ -0,0 +1,105 @@
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use anyhow::{Error, Result};
+use chrono::{DateTime, Local};
+
+use crate::config::CommandConfig;
+use super::{Alert, Notifier};
+
+
+/// Runs a local program for every alert
+///
+/// The alert is passed in the environment: ALERT_EVENT (motion, armed, disarmed, camera_lost,
+/// camera_recovered, config_reloaded or config_error),
+/// ALERT_CAMERA, ALERT_CLIP, ALERT_ZONES and ALERT_LABELS (comma separated), ALERT_MESSAGE
+/// and ALERT_TIME.
+/// Variables that don't apply are empty.
+pub struct CommandNotifier {
+    name: String,
+    program: String,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+
+impl CommandNotifier {
+    pub fn new(config: &CommandConfig) -> Self {
+        Self {
+            name: format!("Command {}", config.program),
+            program: config.program.clone(),
+            args: config.args.clone(),
+            timeout: Duration::from_secs(config.timeout),
+        }
+    }
+}
+
+
+impl Notifier for CommandNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&mut self, alert: &Alert, time: DateTime<Local>) -> Result<()> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .env("ALERT_EVENT", alert.kind())
+            .env("ALERT_CAMERA", alert.camera().unwrap_or(""))
+            .env("ALERT_CLIP", alert.clip().unwrap_or(""))
+            .env("ALERT_ZONES", alert.zones().join(","))
+            .env("ALERT_LABELS", alert.labels().iter().map(|l| l.name.as_str()).collect::<Vec<_>>().join(","))
+            .env("ALERT_MESSAGE", alert.message())
+            .env("ALERT_TIME", time.to_rfc3339())
+            .stdin(Stdio::null())
+            .spawn()?;
+
+        let started = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait()? {
+                if !status.success() {
+                    return Err(Error::msg(format!("Exited with {}", status)))
+                }
+                return Ok(())
+            }
+            if started.elapsed() > self.timeout {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(Error::msg("Timed out"))
+            }
+            sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use crate::config::CommandConfig;
+    use crate::signals::Capture;
+    use super::super::{Alert, Notifier};
+    use super::CommandNotifier;
+
+
+    #[test]
+    fn passes_alert_in_environment() {
+        let mut notifier = CommandNotifier::new(&CommandConfig {
+            enabled: true,
+            program: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                r#"test "$ALERT_EVENT" = motion && test "$ALERT_CAMERA" = yard && test "$ALERT_ZONES" = gate,door"#.to_string(),
+            ],
+            timeout: 5,
+        });
+        let alert = Alert::Motion(Capture {
+            camera: "yard".to_string(),
+            path: "clips/1.mp4".to_string(),
+            zones: vec!["gate".to_string(), "door".to_string()],
+            labels: Vec::new(),
+            notify: true,
+        });
+        assert!(notifier.notify(&alert, Local::now()).is_ok());
+        assert!(notifier.notify(&Alert::Armed(None), Local::now()).is_err());
+    }
+}
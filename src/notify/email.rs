@@ -0,0 +1,94 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use lettre::message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use log::*;
+
+use crate::config::EmailConfig;
+use super::{Alert, Notifier};
+
+
+// Longest wait for the SMTP server on any single step
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+
+/// Emails every alert, with the clip attached to motion alerts
+pub struct EmailNotifier {
+    name: String,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    attach_clip: bool,
+    // Bytes
+    max_attachment_size: u64,
+    transport: SmtpTransport,
+}
+
+
+impl EmailNotifier {
+    pub fn new(config: &EmailConfig) -> Result<Self> {
+        let mut transport = SmtpTransport::starttls_relay(&config.server)?
+            .port(config.port)
+            .timeout(Some(SMTP_TIMEOUT));
+        if !config.username.is_empty() {
+            transport = transport.credentials(Credentials::new(config.username.clone(), config.password.clone()));
+        }
+
+        Ok(Self {
+            name: format!("Email {}", config.server),
+            from: config.from.parse()?,
+            to: config.to.iter().map(|to| to.parse()).collect::<Result<_, _>>()?,
+            attach_clip: config.attach_clip,
+            max_attachment_size: config.max_attachment_size * 1024 * 1024,
+            transport: transport.build(),
+        })
+    }
+
+    fn attachment(&self, path: &str) -> Result<Option<SinglePart>> {
+        let size = match fs::metadata(path) {
+            Ok(metadata) => metadata.len(),
+            // Already removed by the retention policy
+            Err(_) => return Ok(None),
+        };
+        if size > self.max_attachment_size {
+            info!("Clip {} is too large to attach ({} bytes)", path, size);
+            return Ok(None)
+        }
+
+        let filename = Path::new(path).file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "clip".to_string());
+        let content_type = ContentType::parse("application/octet-stream")?;
+        Ok(Some(Attachment::new(filename).body(fs::read(path)?, content_type)))
+    }
+}
+
+
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&mut self, alert: &Alert, time: DateTime<Local>) -> Result<()> {
+        let text = format!("{}\n\n{}", alert.message(), time.format("%Y-%m-%d %H:%M:%S"));
+        let mut body = MultiPart::mixed().singlepart(SinglePart::plain(text));
+        if let (true, Some(path)) = (self.attach_clip, alert.clip()) {
+            if let Some(attachment) = self.attachment(path)? {
+                body = body.singlepart(attachment);
+            }
+        }
+
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(alert.message());
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+
+        self.transport.send(&message.multipart(body)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+pub mod notifier;
+pub mod webhook;
+pub mod email;
+pub mod command;
+
+pub use notifier::*;
+pub use webhook::*;
+pub use email::*;
+pub use command::*;
@@ -0,0 +1,205 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::*;
+
+use crate::config::NotifyConfig;
+use crate::signals::*;
+use super::{CommandNotifier, EmailNotifier, WebhookNotifier};
+
+
+// How long notifiers may take to deliver the alerts queued at shutdown
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+
+/// Something worth telling the user about
+#[derive(Clone, Debug)]
+pub enum Alert {
+    Motion(Capture),
+    // Camera name, or None for all cameras
+    Armed(Option<String>),
+    Disarmed(Option<String>),
+    // Camera name and reason
+    CameraLost(String, String),
+    CameraRecovered(String),
+    // Camera name
+    ConfigReloaded(String),
+    ConfigError(String),
+}
+
+
+impl Alert {
+    /// The alert carried by a signal; motion captured with notifications suppressed is not one
+    pub fn from_signal(signal: &Signal) -> Option<Self> {
+        match signal {
+            Signal::MotionCaptured(capture) if capture.notify => Some(Self::Motion(capture.clone())),
+            Signal::Arm(camera) => Some(Self::Armed(camera.clone())),
+            Signal::Disarm(camera) => Some(Self::Disarmed(camera.clone())),
+            Signal::CameraLost(camera, reason) => Some(Self::CameraLost(camera.clone(), reason.clone())),
+            Signal::CameraRecovered(camera) => Some(Self::CameraRecovered(camera.clone())),
+            Signal::ConfigReloaded(camera) => Some(Self::ConfigReloaded(camera.clone())),
+            Signal::ConfigError(error) => Some(Self::ConfigError(error.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Motion(_) => "motion",
+            Self::Armed(_) => "armed",
+            Self::Disarmed(_) => "disarmed",
+            Self::CameraLost(..) => "camera_lost",
+            Self::CameraRecovered(_) => "camera_recovered",
+            Self::ConfigReloaded(_) => "config_reloaded",
+            Self::ConfigError(_) => "config_error",
+        }
+    }
+
+    pub fn camera(&self) -> Option<&str> {
+        match self {
+            Self::Motion(capture) => Some(&capture.camera),
+            Self::Armed(camera) | Self::Disarmed(camera) => camera.as_deref(),
+            Self::CameraLost(camera, _) | Self::CameraRecovered(camera) | Self::ConfigReloaded(camera) => Some(camera),
+            Self::ConfigError(_) => None,
+        }
+    }
+
+    /// Path of the saved clip, for motion alerts
+    pub fn clip(&self) -> Option<&str> {
+        match self {
+            Self::Motion(capture) => Some(&capture.path),
+            _ => None,
+        }
+    }
+
+    pub fn zones(&self) -> &[String] {
+        match self {
+            Self::Motion(capture) => &capture.zones,
+            _ => &[],
+        }
+    }
+
+    /// What was recognised in the clip, for motion alerts
+    pub fn labels(&self) -> &[Label] {
+        match self {
+            Self::Motion(capture) => &capture.labels,
+            _ => &[],
+        }
+    }
+
+    /// Human readable one-line description
+    pub fn message(&self) -> String {
+        let cameras = self.camera().unwrap_or("all cameras");
+        match self {
+            Self::Motion(_) => {
+                let mut text = format!("Detected motion on {}", cameras);
+                if !self.zones().is_empty() {
+                    text.push_str(&format!(" in {}", self.zones().join(", ")));
+                }
+                if !self.labels().is_empty() {
+                    let labels: Vec<String> = self.labels().iter()
+                        .map(|label| format!("{} ({:.2})", label.name, label.confidence))
+                        .collect();
+                    text.push_str(&format!(": {}", labels.join(", ")));
+                }
+                text
+            }
+            Self::Armed(_) => format!("Armed {}", cameras),
+            Self::Disarmed(_) => format!("Disarmed {}", cameras),
+            Self::CameraLost(_, reason) => format!("Lost {}: {}", cameras, reason),
+            Self::CameraRecovered(_) => format!("Reconnected {}", cameras),
+            Self::ConfigReloaded(_) => format!("Reloaded configuration of {}", cameras),
+            Self::ConfigError(error) => format!("Cannot reload configuration: {}", error),
+        }
+    }
+}
+
+
+/// Delivers alerts somewhere
+pub trait Notifier: Send {
+    /// Used in log messages
+    fn name(&self) -> &str;
+
+    fn notify(&mut self, alert: &Alert, time: DateTime<Local>) -> Result<()>;
+}
+
+
+/// Builds the enabled notifiers
+pub fn notifiers(config: &NotifyConfig) -> Result<Vec<Box<dyn Notifier>>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+
+    for webhook in config.webhooks.iter().filter(|c| c.enabled) {
+        notifiers.push(Box::new(WebhookNotifier::new(webhook)?));
+    }
+    for email in config.email.iter().filter(|c| c.enabled) {
+        notifiers.push(Box::new(EmailNotifier::new(email)?));
+    }
+    for command in config.commands.iter().filter(|c| c.enabled) {
+        notifiers.push(Box::new(CommandNotifier::new(command)));
+    }
+
+    Ok(notifiers)
+}
+
+
+/// Passes alerts from the bus to every notifier until all cameras have stopped
+///
+/// Clips finished during shutdown are still notified about.
+/// Every notifier runs on its own thread, so a slow or failing one doesn't hold back the others.
+pub fn run(notifiers: Vec<Box<dyn Notifier>>, receiver: Receiver) -> Result<()> {
+    let (done_sender, done) = crossbeam_channel::unbounded::<String>();
+    let mut workers = Vec::new();
+    for notifier in notifiers {
+        let (sender, alerts) = crossbeam_channel::unbounded::<(Alert, DateTime<Local>)>();
+        let done_sender = done_sender.clone();
+        thread::Builder::new()
+            .name(notifier.name().to_string())
+            .spawn(move || {
+                let name = notifier.name().to_string();
+                worker(notifier, alerts);
+                done_sender.send(name).ok();
+            })?;
+        workers.push(sender);
+    }
+    drop(done_sender);
+
+    for signal in receiver.iter() {
+        if let Signal::CamerasStopped = signal {
+            break
+        }
+        let alert = match Alert::from_signal(&signal) {
+            Some(alert) => alert,
+            None => continue,
+        };
+        let time = Local::now();
+        for worker in &workers {
+            worker.send((alert.clone(), time)).ok();
+        }
+    }
+
+    // Let the notifiers finish what they have queued, but don't wait on one that hangs
+    let mut pending = workers.len();
+    drop(workers);
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    while pending > 0 {
+        match done.recv_deadline(deadline) {
+            Ok(_) => pending -= 1,
+            Err(_) => {
+                warn!("{} notifiers did not finish in time", pending);
+                break
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+fn worker(mut notifier: Box<dyn Notifier>, alerts: crossbeam_channel::Receiver<(Alert, DateTime<Local>)>) {
+    for (alert, time) in alerts.iter() {
+        if let Err(e) = notifier.notify(&alert, time) {
+            warn!("{} notifier failed: {}", notifier.name(), e);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+use anyhow::{Error, Result};
+use chrono::{DateTime, Local};
+use reqwest::blocking::Client;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Serialize;
+
+use crate::config::WebhookConfig;
+use super::{Alert, Notifier};
+
+
+/// Body of the webhook request
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: &'a str,
+    camera: Option<&'a str>,
+    clip: Option<&'a str>,
+    zones: &'a [String],
+    labels: Vec<PayloadLabel<'a>>,
+    message: String,
+    time: String,
+}
+
+
+#[derive(Serialize)]
+struct PayloadLabel<'a> {
+    name: &'a str,
+    confidence: f64,
+}
+
+
+/// POSTs every alert as JSON to a URL
+pub struct WebhookNotifier {
+    name: String,
+    url: String,
+    client: Client,
+}
+
+
+impl WebhookNotifier {
+    pub fn new(config: &WebhookConfig) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            headers.insert(HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(value)?);
+        }
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            name: format!("Webhook {}", config.url),
+            url: config.url.clone(),
+            client,
+        })
+    }
+}
+
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&mut self, alert: &Alert, time: DateTime<Local>) -> Result<()> {
+        let payload = Payload {
+            event: alert.kind(),
+            camera: alert.camera(),
+            clip: alert.clip(),
+            zones: alert.zones(),
+            labels: alert.labels().iter()
+                .map(|label| PayloadLabel { name: &label.name, confidence: label.confidence })
+                .collect(),
+            message: alert.message(),
+            time: time.to_rfc3339(),
+        };
+        let response = self.client.post(&self.url).json(&payload).send()?;
+        if !response.status().is_success() {
+            return Err(Error::msg(format!("Server responded with {}", response.status())))
+        }
+        Ok(())
+    }
+}
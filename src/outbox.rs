@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use log::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::fs::write_atomically;
+
+
+/// A FIFO queue persisted to disk as JSON lines, so pending items survive restarts
+///
+/// Every change rewrites the file; the queue is expected to stay small.
+pub struct Outbox<T> {
+    path: PathBuf,
+    queue: VecDeque<T>,
+}
+
+
+impl<T> Outbox<T> where T: Serialize + DeserializeOwned {
+    /// Opens the outbox at `path`, loading the items left from a previous run
+    pub fn open(path: &str) -> Result<Self> {
+        let path = PathBuf::from(path);
+        let mut queue = VecDeque::new();
+
+        if path.exists() {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() { continue }
+                match serde_json::from_str(&line) {
+                    Ok(item) => queue.push_back(item),
+                    Err(e) => warn!("Skipping unreadable outbox entry {:?}: {}", line, e),
+                }
+            }
+            if !queue.is_empty() {
+                info!("Loaded {} pending items from {:?}", queue.len(), path);
+            }
+        }
+
+        Ok(Self { path, queue })
+    }
+
+    pub fn push(&mut self, item: T) -> Result<()> {
+        self.queue.push_back(item);
+        self.persist()
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.queue.front()
+    }
+
+    /// Removes the front item once it has been delivered (or given up on)
+    pub fn pop(&mut self) -> Result<Option<T>> {
+        let item = self.queue.pop_front();
+        self.persist()?;
+        Ok(item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn persist(&self) -> Result<()> {
+        if let Some(folder) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(folder)?;
+        }
+
+        write_atomically(&self.path, |file| {
+            for item in &self.queue {
+                writeln!(file, "{}", serde_json::to_string(item)?)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+
+/// Exponential backoff between delivery attempts
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+    next_attempt: Instant,
+}
+
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            current: initial,
+            next_attempt: Instant::now(),
+        }
+    }
+
+    /// Whether the next attempt may be made now
+    pub fn ready(&self) -> bool {
+        Instant::now() >= self.next_attempt
+    }
+
+    /// Schedules the next attempt after a failure, doubling the delay up to `max`
+    pub fn fail(&mut self) -> Duration {
+        let delay = self.current;
+        self.next_attempt = Instant::now() + delay;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+        self.next_attempt = Instant::now();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{Backoff, Outbox};
+
+
+    fn outbox_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("outbox-test-{}-{}.jsonl", name, std::process::id()));
+        std::fs::remove_file(&path).ok();
+        path.to_string_lossy().to_string()
+    }
+
+
+    #[test]
+    fn outbox_survives_reopen_in_order() {
+        let path = outbox_path("reopen");
+        {
+            let mut outbox: Outbox<String> = Outbox::open(&path).unwrap();
+            outbox.push("first".to_string()).unwrap();
+            outbox.push("second".to_string()).unwrap();
+            outbox.push("third".to_string()).unwrap();
+            assert_eq!(outbox.pop().unwrap(), Some("first".to_string()));
+        }
+        let mut outbox: Outbox<String> = Outbox::open(&path).unwrap();
+        assert_eq!(outbox.len(), 2);
+        assert_eq!(outbox.pop().unwrap(), Some("second".to_string()));
+        assert_eq!(outbox.pop().unwrap(), Some("third".to_string()));
+        assert!(outbox.is_empty());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        assert!(backoff.ready());
+        assert_eq!(backoff.fail(), Duration::from_secs(1));
+        assert!(!backoff.ready());
+        assert_eq!(backoff.fail(), Duration::from_secs(2));
+        assert_eq!(backoff.fail(), Duration::from_secs(4));
+        assert_eq!(backoff.fail(), Duration::from_secs(5));
+        backoff.reset();
+        assert!(backoff.ready());
+        assert_eq!(backoff.fail(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::time::{Duration, SystemTime};
+use anyhow::Result;
+use crossbeam_channel::RecvTimeoutError;
+use log::*;
+
+use crate::config::Config;
+use crate::signals::*;
+
+
+/// How often the config file is checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+
+/// Watches the config file until shutdown, sending ReloadConfig whenever it changes
+///
+/// Only camera settings are reloaded; a file that cannot be parsed or fails validation is
+/// reported with ConfigError and the cameras keep their current settings. Changes that need a
+/// restart (compared to the `running` config) are reported with ConfigError too.
+pub fn run(path: String, running: Config, sender: Sender, receiver: Receiver) -> Result<()> {
+    let mut last_modified = modified(&path);
+
+    loop {
+        match receiver.recv_timeout(CHECK_INTERVAL) {
+            Ok(Signal::Shutdown) | Err(RecvTimeoutError::Disconnected) => break,
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        let current = modified(&path);
+        if current == last_modified {
+            continue
+        }
+        last_modified = current;
+
+        info!("{} has changed, reloading", path);
+        match Config::load(&path).and_then(|config| config.validate().into_result().map(|_| config)) {
+            Ok(config) => {
+                let changes = needs_restart(&running, &config);
+                if !changes.is_empty() {
+                    let error = format!("{} need a restart", changes.join(", "));
+                    warn!("Cannot apply all of {}: {}", path, error);
+                    sender.send(Signal::ConfigError(error))?;
+                }
+                sender.send(Signal::ReloadConfig(config.cameras))?
+            }
+            Err(e) => {
+                warn!("Cannot reload {}: {}", path, e);
+                sender.send(Signal::ConfigError(e.to_string()))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Settings changed in `reloaded` that only take effect after a restart
+fn needs_restart(running: &Config, reloaded: &Config) -> Vec<String> {
+    let mut changes = Vec::new();
+    for camera in &reloaded.cameras {
+        match running.cameras.iter().find(|c| c.name == camera.name) {
+            None => changes.push(format!("new camera {}", camera.name)),
+            Some(current) if current.source != camera.source || current.reconnect != camera.reconnect => {
+                changes.push(format!("source of {}", camera.name))
+            }
+            Some(_) => {}
+        }
+    }
+    for camera in running.cameras.iter().filter(|c| !reloaded.cameras.iter().any(|r| r.name == c.name)) {
+        changes.push(format!("removed camera {}", camera.name));
+    }
+
+    if running.headless != reloaded.headless {
+        changes.push("headless".to_string());
+    }
+    if running.arming != reloaded.arming {
+        changes.push("arming".to_string());
+    }
+    if running.telegram != reloaded.telegram {
+        changes.push("telegram".to_string());
+    }
+    if running.notify != reloaded.notify {
+        changes.push("notify".to_string());
+    }
+    changes
+}
+
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, DiffConfig, SourceConfig};
+    use super::needs_restart;
+
+
+    fn camera(name: &str) -> DiffConfig {
+        DiffConfig { name: name.to_string(), ..DiffConfig::default() }
+    }
+
+
+    #[test]
+    fn reports_only_what_cannot_be_applied_live() {
+        let running = Config { cameras: vec![camera("porch"), camera("yard")], ..Config::default() };
+
+        let mut reloaded = Config { cameras: vec![camera("porch"), camera("yard")], ..Config::default() };
+        reloaded.cameras[0].threshold = 20;
+        assert!(needs_restart(&running, &reloaded).is_empty());
+
+        reloaded.cameras[1].source = SourceConfig::Url { url: "rtsp://yard".to_string() };
+        reloaded.cameras.push(camera("garage"));
+        reloaded.cameras.remove(0);
+        reloaded.arming.armed_on_start = false;
+        assert_eq!(
+            needs_restart(&running, &reloaded),
+            vec!["source of yard", "new camera garage", "removed camera porch", "arming"]
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+use anyhow::Result;
+
+use crate::cam::configure_detector;
+use crate::config::DiffConfig;
+use crate::cv::{FileSource, FrameSource};
+
+
+/// A stretch of a video with motion in it
+#[derive(Debug)]
+pub struct MotionEvent {
+    // Offsets from the start of the video
+    pub start: Duration,
+    pub end: Duration,
+    pub zones: Vec<String>,
+    // Largest share of the frame that changed
+    pub peak_ratio: f64,
+}
+
+
+/// Runs motion detection on a video file without recording anything
+///
+/// Motion separated by no more than `max_idle_gap` is reported as one event.
+pub fn replay(config: &DiffConfig, path: &str) -> Result<Vec<MotionEvent>> {
+    let mut source = FileSource::new(path)?;
+    let mut detector = configure_detector(config)?;
+    let fps = source.fps().unwrap_or(config.output.fps);
+    let max_idle_gap = Duration::from_secs(config.max_idle_gap);
+
+    let mut events: Vec<MotionEvent> = Vec::new();
+    let mut frame_index: u64 = 0;
+
+    while let Some(frame) = source.read()? {
+        let time = Duration::from_secs_f64(frame_index as f64 / fps);
+        frame_index += 1;
+
+        let result = detector.detect(&frame)?;
+        if !result.is_motion() {
+            continue
+        }
+
+        match events.last_mut() {
+            Some(event) if time - event.end <= max_idle_gap => {
+                event.end = time;
+                event.peak_ratio = event.peak_ratio.max(result.changed_ratio);
+                for zone in result.zones {
+                    if !event.zones.contains(&zone) {
+                        event.zones.push(zone);
+                    }
+                }
+            }
+            _ => events.push(MotionEvent {
+                start: time,
+                end: time,
+                zones: result.zones,
+                peak_ratio: result.changed_ratio,
+            }),
+        }
+    }
+
+    Ok(events)
+}
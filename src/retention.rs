@@ -0,0 +1,298 @@
+use std::fs::{read_dir, remove_file};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use anyhow::Result;
+use crossbeam_channel::RecvTimeoutError;
+use log::*;
+
+use crate::config::{DiffConfig, RetentionConfig};
+use crate::cv::is_in_progress;
+use crate::signals::*;
+
+
+/// Retention policy of one output folder
+#[derive(Debug)]
+pub struct Retention {
+    pub folder: String,
+    // Extension of the clips, see `clip_extension`
+    pub extension: String,
+    pub config: RetentionConfig,
+}
+
+
+impl Retention {
+    /// One policy per output folder; cameras sharing a folder get the strictest of their limits
+    pub fn for_cameras(cameras: &[DiffConfig]) -> Vec<Self> {
+        let mut policies: Vec<Self> = Vec::new();
+        for camera in cameras {
+            let folder = &camera.output.result_folder;
+            let extension = clip_extension(&camera.output.result_filename_format);
+            let config = &camera.output.retention;
+
+            match policies.iter_mut().find(|p| &p.folder == folder && p.extension == extension) {
+                Some(policy) => {
+                    let merged = &mut policy.config;
+                    merged.max_age = strictest(merged.max_age, config.max_age);
+                    merged.max_total_size = strictest(merged.max_total_size, config.max_total_size);
+                    merged.max_files = strictest(merged.max_files, config.max_files);
+                    merged.check_interval = merged.check_interval.min(config.check_interval);
+                }
+                None => policies.push(Self {
+                    folder: folder.clone(),
+                    extension,
+                    config: config.clone(),
+                }),
+            }
+        }
+        policies
+    }
+}
+
+
+fn strictest<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+
+/// A clip in an output folder
+pub struct Clip {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    pub size: u64,
+}
+
+
+/// Periodically deletes old clips of the cameras until shutdown
+///
+/// The policies follow reloaded configs of the running cameras; added cameras need a restart.
+pub fn run(mut cameras: Vec<DiffConfig>, sender: Sender, receiver: Receiver) -> Result<()> {
+    let mut policies = Retention::for_cameras(&cameras);
+    loop {
+        for policy in &policies {
+            match enforce(policy) {
+                Ok(deleted) if !deleted.is_empty() => {
+                    sender.send(Signal::ClipsDeleted(deleted))?;
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Cannot enforce retention on {}: {}", policy.folder, e),
+            }
+        }
+
+        match receiver.recv_timeout(check_interval(&policies)) {
+            Ok(Signal::Shutdown) | Err(RecvTimeoutError::Disconnected) => break,
+            Ok(Signal::ReloadConfig(reloaded)) => {
+                for camera in cameras.iter_mut() {
+                    if let Some(config) = reloaded.iter().find(|c| c.name == camera.name) {
+                        *camera = config.clone();
+                    }
+                }
+                policies = Retention::for_cameras(&cameras);
+                debug!("Retention: policies reloaded: {:?}", policies);
+            }
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+        }
+    }
+
+    Ok(())
+}
+
+
+fn check_interval(policies: &[Retention]) -> Duration {
+    policies.iter()
+        .map(|p| Duration::from_secs(p.config.check_interval))
+        .min()
+        .unwrap_or(Duration::from_secs(600))
+}
+
+
+/// Deletes clips violating the policy, oldest first; returns the deleted paths
+pub fn enforce(policy: &Retention) -> Result<Vec<String>> {
+    // Nothing has been recorded yet
+    if !Path::new(&policy.folder).exists() {
+        return Ok(Vec::new())
+    }
+
+    let clips = list_clips(&policy.folder, &policy.extension)?;
+    let expired = select_expired(clips, &policy.config, SystemTime::now());
+
+    let mut deleted = Vec::new();
+    for clip in expired {
+        let path = clip.path.to_string_lossy().to_string();
+        match remove_file(&clip.path) {
+            Ok(_) => {
+                info!("Retention: deleted {}", path);
+                // Tracks saved along with the clip
+                let metadata = clip.path.with_extension("json");
+                if metadata.exists() {
+                    remove_file(&metadata).ok();
+                }
+                deleted.push(path);
+            }
+            Err(e) => warn!("Retention: cannot delete {}: {}", path, e),
+        }
+    }
+    Ok(deleted)
+}
+
+
+/// Total size of the clips in a folder, in bytes
+pub fn folder_size(folder: &str, extension: &str) -> Result<u64> {
+    if !Path::new(folder).exists() {
+        return Ok(0)
+    }
+    Ok(list_clips(folder, extension)?.iter().map(|c| c.size).sum())
+}
+
+
+/// Extension of the clips named by a `result_filename_format`, lowercase and without the dot
+pub fn clip_extension(filename_format: &str) -> String {
+    Path::new(filename_format).extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+
+/// Clips in an output folder, in no particular order
+///
+/// Only files with the clip extension count; metadata, clips still being recorded and anything
+/// else kept in the folder are left alone.
+pub fn list_clips(folder: &str, extension: &str) -> Result<Vec<Clip>> {
+    let mut clips = Vec::new();
+    for entry in read_dir(folder)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() { continue }
+        let path = entry.path();
+        if is_in_progress(&path) { continue }
+        let file_extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+        if file_extension != extension { continue }
+        clips.push(Clip {
+            path: entry.path(),
+            modified: metadata.modified()?,
+            size: metadata.len(),
+        });
+    }
+    Ok(clips)
+}
+
+
+fn select_expired(mut clips: Vec<Clip>, config: &RetentionConfig, now: SystemTime) -> Vec<Clip> {
+    clips.sort_by_key(|c| c.modified);
+
+    let mut expired = Vec::new();
+    let mut total_size: u64 = clips.iter().map(|c| c.size).sum();
+    let mut count = clips.len();
+
+    for clip in clips {
+        let too_old = match config.max_age {
+            Some(hours) => now.duration_since(clip.modified)
+                .map(|age| age > Duration::from_secs(hours * 3600))
+                .unwrap_or(false),
+            None => false,
+        };
+        let too_many = config.max_files.map_or(false, |max| count > max);
+        let too_large = config.max_total_size.map_or(false, |max| total_size > max * 1024 * 1024);
+
+        if !(too_old || too_many || too_large) {
+            // Clips are sorted by age, so the rest are within all limits
+            break;
+        }
+
+        count -= 1;
+        total_size -= clip.size;
+        expired.push(clip);
+    }
+
+    expired
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+    use crate::config::{DiffConfig, RetentionConfig};
+use crate::cv::is_in_progress;
+    use super::{clip_extension, list_clips, select_expired, Clip, Retention};
+
+
+    fn clip(name: &str, age_hours: u64, size_mb: u64, now: SystemTime) -> Clip {
+        Clip {
+            path: PathBuf::from(name),
+            modified: now - Duration::from_secs(age_hours * 3600),
+            size: size_mb * 1024 * 1024,
+        }
+    }
+
+    fn names(clips: Vec<Clip>) -> Vec<String> {
+        clips.into_iter().map(|c| c.path.to_string_lossy().to_string()).collect()
+    }
+
+
+    #[test]
+    fn deletes_oldest_first() {
+        let now = SystemTime::now();
+        let clips = vec![
+            clip("new", 1, 10, now),
+            clip("oldest", 30, 10, now),
+            clip("old", 20, 10, now),
+        ];
+        let config = RetentionConfig {
+            max_age: Some(24),
+            max_total_size: Some(15),
+            max_files: None,
+            ..RetentionConfig::default()
+        };
+        assert_eq!(names(select_expired(clips, &config, now)), vec!["oldest", "old"]);
+    }
+
+    #[test]
+    fn keeps_everything_within_limits() {
+        let now = SystemTime::now();
+        let clips = vec![clip("a", 1, 1, now), clip("b", 2, 1, now)];
+        let config = RetentionConfig {
+            max_age: Some(24),
+            max_total_size: Some(100),
+            max_files: Some(2),
+            ..RetentionConfig::default()
+        };
+        assert!(select_expired(clips, &config, now).is_empty());
+    }
+
+    #[test]
+    fn lists_only_clips() {
+        let folder = std::env::temp_dir().join(format!("retention-test-{}", std::process::id()));
+        remove_dir_all(&folder).ok();
+        create_dir_all(&folder).unwrap();
+        for name in ["a.mp4", "b.MP4", "a.json", "c.mp4.tmp", ".d.mp4", "notes.txt"] {
+            write(folder.join(name), b"clip").unwrap();
+        }
+
+        let extension = clip_extension("%Y-%m-%d-%H-%M-%S.mp4");
+        let mut names: Vec<String> = list_clips(folder.to_str().unwrap(), &extension).unwrap().into_iter()
+            .map(|c| c.path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["a.mp4", "b.MP4"]);
+        remove_dir_all(&folder).ok();
+    }
+
+    #[test]
+    fn cameras_sharing_a_folder_get_one_policy() {
+        let mut door = DiffConfig::default();
+        door.output.retention = RetentionConfig { max_age: Some(48), max_files: Some(10), ..RetentionConfig::default() };
+        let mut yard = DiffConfig::default();
+        yard.output.retention = RetentionConfig { max_age: Some(24), max_total_size: Some(500), ..RetentionConfig::default() };
+
+        let policies = Retention::for_cameras(&[door, yard]);
+        assert_eq!(policies.len(), 1);
+        assert_eq!(policies[0].extension, "mp4");
+        assert_eq!(policies[0].config.max_age, Some(24));
+        assert_eq!(policies[0].config.max_files, Some(10));
+        assert_eq!(policies[0].config.max_total_size, Some(500));
+    }
+}
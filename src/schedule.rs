@@ -0,0 +1,99 @@
+use std::time::Duration;
+use anyhow::Result;
+use chrono::{Datelike, Local, NaiveDateTime};
+use crossbeam_channel::RecvTimeoutError;
+use log::*;
+
+use crate::config::ScheduleWindowConfig;
+use crate::signals::*;
+
+
+/// How often the schedule is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+
+/// Arms and disarms all cameras according to the weekly schedule until shutdown
+///
+/// Only transitions are announced, so a manual /arm or /disarm holds until the next one.
+pub fn run(schedule: Vec<ScheduleWindowConfig>, sender: Sender, receiver: Receiver) -> Result<()> {
+    let mut armed: Option<bool> = None;
+
+    loop {
+        let should_be_armed = is_armed_at(&schedule, Local::now().naive_local());
+        if armed != Some(should_be_armed) {
+            info!("Schedule: {}", if should_be_armed { "arming" } else { "disarming" });
+            sender.send(match should_be_armed {
+                true => Signal::Arm(None),
+                false => Signal::Disarm(None),
+            })?;
+            armed = Some(should_be_armed);
+        }
+
+        match receiver.recv_timeout(CHECK_INTERVAL) {
+            Ok(Signal::Shutdown) | Err(RecvTimeoutError::Disconnected) => break,
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+        }
+    }
+
+    Ok(())
+}
+
+
+pub fn is_armed_at(schedule: &[ScheduleWindowConfig], now: NaiveDateTime) -> bool {
+    schedule.iter().any(|window| window_contains(window, now))
+}
+
+
+fn window_contains(window: &ScheduleWindowConfig, now: NaiveDateTime) -> bool {
+    let day = now.weekday();
+    let time = now.time();
+
+    if window.from <= window.to {
+        return window.days.contains(&day) && window.from <= time && time < window.to
+    }
+
+    // Overnight window: the part after midnight belongs to the previous day
+    (window.days.contains(&day) && time >= window.from)
+        || (window.days.contains(&day.pred()) && time < window.to)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+    use crate::config::ScheduleWindowConfig;
+    use super::is_armed_at;
+
+
+    fn window(days: Vec<Weekday>, from: (u32, u32), to: (u32, u32)) -> ScheduleWindowConfig {
+        ScheduleWindowConfig {
+            days,
+            from: NaiveTime::from_hms_opt(from.0, from.1, 0).unwrap(),
+            to: NaiveTime::from_hms_opt(to.0, to.1, 0).unwrap(),
+        }
+    }
+
+    // 2022-11-14 is a Monday
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2022, 11, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+
+    #[test]
+    fn daytime_window() {
+        let schedule = vec![window(vec![Weekday::Mon, Weekday::Tue], (9, 0), (18, 0))];
+        assert!(is_armed_at(&schedule, at(14, 9, 0)));
+        assert!(!is_armed_at(&schedule, at(14, 18, 0)));
+        assert!(!is_armed_at(&schedule, at(14, 8, 59)));
+        assert!(!is_armed_at(&schedule, at(16, 12, 0)));
+    }
+
+    #[test]
+    fn overnight_window() {
+        let schedule = vec![window(vec![Weekday::Fri], (22, 0), (6, 0))];
+        assert!(is_armed_at(&schedule, at(18, 23, 0)));
+        assert!(is_armed_at(&schedule, at(19, 5, 59)));
+        assert!(!is_armed_at(&schedule, at(19, 6, 0)));
+        assert!(!is_armed_at(&schedule, at(18, 5, 0)));
+    }
+}
@@ -1,4 +1,6 @@
+use chrono::{DateTime, Local};
 use crossbeam_channel;
+use crate::config::DiffConfig;
 
 
 pub type Sender = crossbeam_channel::Sender<Signal>;
@@ -7,8 +9,80 @@ pub type Receiver = crossbeam_channel::Receiver<Signal>;
 
 #[derive(Clone)]
 pub enum Signal {
-    StartCamera,
-    StopCamera,
-    MotionCaptureStarted,
-    MotionCaptured(String),
-}
\ No newline at end of file
+    // Camera name, or None for all cameras
+    StartCamera(Option<String>),
+    StopCamera(Option<String>),
+    // Disarmed cameras keep watching, with recordings and/or notifications suppressed
+    Arm(Option<String>),
+    Disarm(Option<String>),
+    MotionCaptureStarted(String),
+    MotionCaptured(Capture),
+    // Camera name and why it was lost; it is reconnected with backoff
+    CameraLost(String, String),
+    CameraRecovered(String),
+    // Paths of clips removed by the retention policy
+    ClipsDeleted(Vec<String>),
+    // Every camera answers with a StatusReport
+    StatusRequest,
+    StatusReport(CameraStatus),
+    // Camera name, or None for all cameras; answered with Snapshot
+    SnapshotRequest(Option<String>),
+    Snapshot(Snapshot),
+    // Camera name; sent instead of Snapshot while the camera has no frame yet
+    SnapshotUnavailable(String),
+    // config.toml has changed; every camera picks its entry by name
+    ReloadConfig(Vec<DiffConfig>),
+    // Camera name
+    ConfigReloaded(String),
+    // The changed config could not be read or applied
+    ConfigError(String),
+    // Stop all threads; cameras finish their clips in progress
+    Shutdown,
+    // Sent once every camera thread has ended; notifiers deliver what is left and stop
+    CamerasStopped,
+}
+
+
+/// A saved motion clip
+#[derive(Clone, Debug)]
+pub struct Capture {
+    pub camera: String,
+    pub path: String,
+    // Detection zones the motion happened in
+    pub zones: Vec<String>,
+    // What was recognised in the clip, e.g. "person"
+    pub labels: Vec<Label>,
+    // False if captured while disarmed with notifications suppressed
+    pub notify: bool,
+}
+
+
+/// A kind of object recognised in a clip, with the highest confidence it was seen with
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub name: String,
+    pub confidence: f64,
+}
+
+
+/// State of a camera thread, in response to StatusRequest
+#[derive(Clone, Debug)]
+pub struct CameraStatus {
+    pub camera: String,
+    pub running: bool,
+    pub armed: bool,
+    // Name of the motion detection state
+    pub state: String,
+    pub frames_processed: u64,
+    pub last_motion: Option<DateTime<Local>>,
+    // Total size of the output folder, in bytes
+    pub disk_usage: u64,
+}
+
+
+/// Latest frame of a camera, in response to SnapshotRequest
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub camera: String,
+    pub jpeg: Vec<u8>,
+}
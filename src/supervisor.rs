@@ -0,0 +1,302 @@
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use log::*;
+use opencv::prelude::Mat;
+
+use crate::config::{ReconnectConfig, SourceConfig};
+use crate::cv::*;
+use crate::outbox::Backoff;
+use crate::signals::*;
+
+
+// How long to sleep between reconnection checks while the source is lost
+const IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
+
+/// Outcome of reading from a supervised source
+pub enum SourceRead {
+    Frame(Mat),
+    // The source has just been lost; a clip in progress should be finished
+    Lost,
+    // The source is lost and not reconnected yet
+    Waiting,
+    // A finite source has no more frames
+    Exhausted,
+}
+
+
+// Opens the frame source described by the config
+type Opener = Box<dyn FnMut(&SourceConfig) -> Result<Box<dyn FrameSource>>>;
+
+
+/// Wraps the frame source of a camera, reconnecting live sources that drop out
+///
+/// A live source (a device or a stream) is lost when a read fails or its timestamp stops
+/// advancing; it is then reopened with exponential backoff. A reopened source counts as
+/// recovered only once it gives a frame, as devices and streams often open fine and then fail
+/// to read. CameraLost and CameraRecovered are sent on the bus. Errors of files and image
+/// folders are passed through.
+pub struct SupervisedSource {
+    camera: String,
+    config: SourceConfig,
+    opener: Opener,
+    source: Option<Box<dyn FrameSource>>,
+    // The source was reopened after a loss and hasn't given a frame yet
+    recovering: bool,
+    fps: Option<f64>,
+    sender: Sender,
+    backoff: Backoff,
+    stall_timeout: Duration,
+    exhausted: bool,
+    // Last stream timestamp and when it was first seen
+    last_timestamp: Option<(f64, Instant)>,
+}
+
+
+impl SupervisedSource {
+    /// Opens the source; a live source that can't be opened yet is retried in the background
+    pub fn open(camera: &str, config: &SourceConfig, reconnect: &ReconnectConfig, sender: Sender) -> Result<Self> {
+        Self::open_with(camera, config, reconnect, sender, Box::new(open_source))
+    }
+
+    fn open_with(
+        camera: &str,
+        config: &SourceConfig,
+        reconnect: &ReconnectConfig,
+        sender: Sender,
+        mut opener: Opener) -> Result<Self>
+    {
+        let opened = opener(config);
+        let mut supervised = Self {
+            camera: camera.to_string(),
+            config: config.clone(),
+            opener,
+            source: None,
+            recovering: false,
+            fps: None,
+            sender,
+            backoff: Backoff::new(
+                Duration::from_secs(reconnect.initial_delay),
+                Duration::from_secs(reconnect.max_delay),
+            ),
+            stall_timeout: Duration::from_secs(reconnect.stall_timeout),
+            exhausted: false,
+            last_timestamp: None,
+        };
+
+        match opened {
+            Ok(source) => {
+                supervised.fps = source.fps();
+                supervised.source = Some(source);
+            }
+            Err(e) if supervised.is_live() => {
+                supervised.backoff.fail();
+                supervised.lost(&e.to_string())?;
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(supervised)
+    }
+
+    pub fn fps(&self) -> Option<f64> {
+        self.fps
+    }
+
+    pub fn is_live(&self) -> bool {
+        matches!(self.config, SourceConfig::Device { .. } | SourceConfig::Url { .. })
+    }
+
+    pub fn read(&mut self) -> Result<SourceRead> {
+        if self.exhausted {
+            return Ok(SourceRead::Exhausted)
+        }
+
+        let source = match &mut self.source {
+            Some(source) => source,
+            None => {
+                self.reconnect()?;
+                return Ok(SourceRead::Waiting)
+            }
+        };
+
+        match source.read() {
+            Ok(Some(frame)) => {
+                let timestamp = source.timestamp();
+                if self.is_stalled(timestamp) {
+                    self.lost("stream timestamp stopped advancing")?;
+                    return Ok(SourceRead::Lost)
+                }
+                if self.recovering {
+                    self.recovered()?;
+                }
+                Ok(SourceRead::Frame(frame))
+            }
+            Ok(None) => {
+                self.exhausted = true;
+                Ok(SourceRead::Exhausted)
+            }
+            // Reopened, but still not giving frames; the camera is already reported lost
+            Err(e) if self.recovering => {
+                self.source = None;
+                let delay = self.backoff.fail();
+                warn!("[{}] Reconnected but cannot read, retrying in {:?}: {}", self.camera, delay, e);
+                Ok(SourceRead::Waiting)
+            }
+            Err(e) if self.is_live() => {
+                self.lost(&e.to_string())?;
+                Ok(SourceRead::Lost)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn is_stalled(&mut self, timestamp: Option<f64>) -> bool {
+        let timestamp = match timestamp {
+            Some(timestamp) => timestamp,
+            None => return false,
+        };
+        match self.last_timestamp {
+            Some((last, since)) if last == timestamp => since.elapsed() > self.stall_timeout,
+            _ => {
+                self.last_timestamp = Some((timestamp, Instant::now()));
+                false
+            }
+        }
+    }
+
+    fn lost(&mut self, reason: &str) -> Result<()> {
+        warn!("[{}] Camera lost: {}", self.camera, reason);
+        self.source = None;
+        self.recovering = false;
+        self.last_timestamp = None;
+        self.sender.send(Signal::CameraLost(self.camera.clone(), reason.to_string()))?;
+        Ok(())
+    }
+
+    fn recovered(&mut self) -> Result<()> {
+        info!("[{}] Camera reconnected", self.camera);
+        self.recovering = false;
+        self.backoff.reset();
+        self.sender.send(Signal::CameraRecovered(self.camera.clone()))?;
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        if !self.backoff.ready() {
+            sleep(IDLE_INTERVAL);
+            return Ok(())
+        }
+
+        match (self.opener)(&self.config) {
+            Ok(source) => {
+                info!("[{}] Camera reopened, waiting for a frame", self.camera);
+                self.source = Some(source);
+                self.recovering = true;
+            }
+            Err(e) => {
+                let delay = self.backoff.fail();
+                warn!("[{}] Cannot reconnect, retrying in {:?}: {}", self.camera, delay, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+
+pub fn open_source(config: &SourceConfig) -> Result<Box<dyn FrameSource>> {
+    let source: Box<dyn FrameSource> = match config {
+        SourceConfig::Device { index, width, height } => {
+            Box::new(DeviceSource::new(*index, *width, *height)?)
+        }
+        SourceConfig::Url { url } => Box::new(UrlSource::new(url)?),
+        SourceConfig::File { path } => Box::new(FileSource::new(path)?),
+        SourceConfig::Images { folder, fps } => Box::new(ImageDirSource::new(folder, *fps)?),
+    };
+    Ok(source)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use anyhow::{Error, Result};
+    use opencv::prelude::Mat;
+    use crate::config::{ReconnectConfig, SourceConfig};
+    use crate::cv::FrameSource;
+    use crate::signals::Signal;
+    use super::{Opener, SourceRead, SupervisedSource};
+
+
+    /// Gives a frame or fails, as scripted
+    struct FakeSource {
+        reads: VecDeque<bool>,
+    }
+
+
+    impl FrameSource for FakeSource {
+        fn read(&mut self) -> Result<Option<Mat>> {
+            match self.reads.pop_front() {
+                Some(true) => Ok(Some(Mat::default())),
+                _ => Err(Error::msg("Failed to read a frame")),
+            }
+        }
+    }
+
+
+    /// Every open succeeds; the n-th opened source reads as `scripts[n]`
+    fn opener(scripts: Vec<Vec<bool>>, opened: Rc<Cell<usize>>) -> Opener {
+        let mut scripts: VecDeque<Vec<bool>> = scripts.into();
+        Box::new(move |_| {
+            opened.set(opened.get() + 1);
+            let reads = scripts.pop_front().unwrap_or_default().into();
+            Ok(Box::new(FakeSource { reads }) as Box<dyn FrameSource>)
+        })
+    }
+
+
+    fn supervise(scripts: Vec<Vec<bool>>, initial_delay: u64) -> (SupervisedSource, Rc<Cell<usize>>, crate::signals::Receiver) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let opened = Rc::new(Cell::new(0));
+        let reconnect = ReconnectConfig { initial_delay, max_delay: 60, stall_timeout: 10 };
+        let config = SourceConfig::Device { index: 0, width: 640, height: 480 };
+        let source = SupervisedSource::open_with("cam", &config, &reconnect, sender, opener(scripts, opened.clone()))
+            .unwrap();
+        (source, opened, receiver)
+    }
+
+
+    #[test]
+    fn source_that_opens_but_cannot_read_is_not_recovered() {
+        let (mut source, opened, receiver) = supervise(vec![vec![false], vec![false], vec![false]], 1);
+
+        assert!(matches!(source.read().unwrap(), SourceRead::Lost));
+        for _ in 0..4 {
+            assert!(matches!(source.read().unwrap(), SourceRead::Waiting));
+        }
+
+        // Reopened once right away, then held back by the backoff
+        assert_eq!(opened.get(), 2);
+        let signals: Vec<Signal> = receiver.try_iter().collect();
+        assert_eq!(signals.len(), 1);
+        assert!(matches!(signals[0], Signal::CameraLost(..)));
+    }
+
+    #[test]
+    fn recovered_after_first_frame() {
+        let (mut source, _, receiver) = supervise(vec![vec![false], vec![true, true]], 0);
+
+        assert!(matches!(source.read().unwrap(), SourceRead::Lost));
+        assert!(matches!(source.read().unwrap(), SourceRead::Waiting));
+        assert!(receiver.try_iter().all(|signal| matches!(signal, Signal::CameraLost(..))));
+
+        assert!(matches!(source.read().unwrap(), SourceRead::Frame(_)));
+        assert!(matches!(source.read().unwrap(), SourceRead::Frame(_)));
+        let signals: Vec<Signal> = receiver.try_iter().collect();
+        assert_eq!(signals.len(), 1);
+        assert!(matches!(signals[0], Signal::CameraRecovered(_)));
+    }
+}
@@ -1,9 +1,11 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use anyhow::Result;
-use log::info;
+use chrono::{DateTime, Local};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
 use teloxide::{
     utils::command::BotCommands,
     Bot,
@@ -13,35 +15,181 @@ use teloxide::{
     types::Update,
     dispatching::UpdateFilterExt,
 };
-use teloxide::types::InputFile;
+use teloxide::dispatching::ShutdownToken;
+use teloxide::types::{InputFile, UserId};
+use teloxide::{ApiError, RequestError};
 use tokio;
 use tokio::time::sleep;
 
 
+use crate::config::{ChatConfig, Role, TelegramConfig};
+use crate::notify::{Alert, Notifier};
+use crate::outbox::{Backoff, Outbox};
 use crate::signals::*;
 
 type Shared<T> = Arc<Mutex<T>>;
 
 
+// How long a chat waits for snapshots after asking
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How long a chat waits for status reports after asking
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Delays between attempts to deliver the outbox while Telegram is unreachable
+const RETRY_INITIAL: Duration = Duration::from_secs(2);
+const RETRY_MAX: Duration = Duration::from_secs(300);
+
+// How long pending notifications are retried on shutdown
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// A notification waiting in the outbox
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag="type", rename_all="lowercase")]
+enum Notification {
+    Text { chat_id: i64, text: String },
+    Video { chat_id: i64, path: String },
+}
+
+
+/// Queues alerts for every subscribed chat; the notificator loop delivers them
+struct TelegramNotifier {
+    subscribers: Vec<ChatId>,
+    outbox: Outbox<Notification>,
+}
+
+
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &str {
+        "Telegram"
+    }
+
+    fn notify(&mut self, alert: &Alert, _time: DateTime<Local>) -> Result<()> {
+        for &chat_id in &self.subscribers {
+            self.outbox.push(Notification::Text { chat_id: chat_id.0, text: alert.message() })?;
+            if let Some(path) = alert.clip() {
+                self.outbox.push(Notification::Video { chat_id: chat_id.0, path: path.to_string() })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
 #[derive(BotCommands, PartialEq, Debug)]
-#[command(rename_rule="lowercase", parse_with="split")]
+#[command(rename_rule="lowercase")]
 enum Command {
-    StopCamera,
-    StartCamera
+    // Optional camera name; all cameras if omitted
+    StopCamera(String),
+    StartCamera(String),
+    Status,
+    // Optional camera name; all cameras if omitted
+    Snapshot(String),
+    Arm(String),
+    Disarm(String),
+}
+
+
+impl Command {
+    fn required_role(&self) -> Role {
+        match self {
+            Command::Status | Command::Snapshot(_) => Role::Viewer,
+            Command::StopCamera(_) | Command::StartCamera(_)
+                | Command::Arm(_) | Command::Disarm(_) => Role::Admin,
+        }
+    }
+}
+
+
+/// Chats and users allowed to talk to the bot
+struct Access {
+    config: TelegramConfig,
+}
+
+
+impl Access {
+    fn new(mut config: TelegramConfig) -> Result<Self> {
+        if config.chats.is_empty() {
+            config.chats.push(ChatConfig {
+                id: std::env::var("CHAT_ID")?.trim().parse()?,
+                role: Role::Admin,
+                subscribed: true,
+            });
+        }
+        Ok(Self { config })
+    }
+
+    /// Role of the message sender, or None if the chat is not authorised
+    ///
+    /// A role given to the user overrides the role of the chat, either way.
+    fn role(&self, msg: &Message) -> Option<Role> {
+        let chat = self.config.chats.iter().find(|c| ChatId(c.id) == msg.chat.id)?;
+        let user_role = msg.from()
+            .and_then(|user| self.config.users.iter().find(|u| UserId(u.id) == user.id))
+            .map(|u| u.role);
+        Some(user_role.unwrap_or(chat.role))
+    }
+
+    fn subscribers(&self) -> Vec<ChatId> {
+        self.config.chats.iter()
+            .filter(|c| c.subscribed)
+            .map(|c| ChatId(c.id))
+            .collect()
+    }
+}
+
+
+/// Chats waiting for answers from the cameras
+#[derive(Default)]
+struct Requests {
+    status: Vec<StatusRequest>,
+    snapshot: Vec<SnapshotRequest>,
+}
+
+
+/// A chat waiting for the status of every camera
+struct StatusRequest {
+    chat_id: ChatId,
+    since: Instant,
+    reports: Vec<CameraStatus>,
 }
 
 
-pub fn run(sender: Sender, receiver: Receiver) -> Result<()>
+/// A chat waiting for snapshots
+struct SnapshotRequest {
+    chat_id: ChatId,
+    // None for all cameras
+    camera: Option<String>,
+    since: Instant,
+    // Cameras whose snapshot was already sent
+    answered: Vec<String>,
+}
+
+
+/// Whether the bot token and at least one chat are available; fails on a malformed CHAT_ID
+pub fn has_credentials(config: &TelegramConfig) -> Result<bool> {
+    let chat_id = match std::env::var("CHAT_ID") {
+        Ok(chat_id) => Some(chat_id.trim().parse::<i64>()
+            .map_err(|e| anyhow::Error::msg(format!("CHAT_ID {:?} is not a chat id: {}", chat_id, e)))?),
+        Err(_) => None,
+    };
+    Ok(std::env::var("TELOXIDE_TOKEN").is_ok() && (!config.chats.is_empty() || chat_id.is_some()))
+}
+
+
+/// Runs the bot; `cameras` are the names of the configured cameras
+pub fn run(config: TelegramConfig, cameras: Vec<String>, sender: Sender, receiver: Receiver) -> Result<()>
 {
     let rt = tokio::runtime::Runtime::new().unwrap();
-    //thread::spawn(move || stupid_thread(s));
-    rt.block_on(start_bot(sender, receiver))?;
+    rt.block_on(start_bot(config, cameras, sender, receiver))?;
     Ok(())
 }
 
 
-pub async fn start_bot(sender: Sender, receiver: Receiver) -> Result<()> {
-    let chat_id: ChatId = ChatId( std::env::var("CHAT_ID")?.parse()? ) ;
+pub async fn start_bot(config: TelegramConfig, cameras: Vec<String>, sender: Sender, receiver: Receiver) -> Result<()> {
+    let access = Arc::new(Access::new(config)?);
+    let requests: Shared<Requests> = Arc::new(Mutex::new(Requests::default()));
 
     let bot = Bot::from_env();
 
@@ -49,41 +197,232 @@ pub async fn start_bot(sender: Sender, receiver: Receiver) -> Result<()> {
         Update::filter_message().endpoint(handle_commands)
     );
 
-    tokio::spawn(notificator_loop(bot.clone(), receiver.clone(), chat_id));
+    let started = Instant::now();
+    let notifier = TelegramNotifier {
+        subscribers: access.subscribers(),
+        outbox: Outbox::open(&access.config.outbox)?,
+    };
 
-    Dispatcher::builder(bot.clone(), handler )
-        .dependencies(deps![sender, chat_id])
-        .build()
-        .dispatch()
-        .await;
+    let mut dispatcher = Dispatcher::builder(bot.clone(), handler )
+        .dependencies(deps![sender, access, requests.clone(), Arc::new(cameras.clone())])
+        .build();
 
-    Ok(())
+    let notificator = tokio::spawn(notificator_loop(
+        bot.clone(), receiver.clone(), notifier, requests, cameras, started, dispatcher.shutdown_token()
+    ));
+
+    dispatcher.dispatch().await;
+
+    notificator.await?
 }
 
 
-async fn notificator_loop(bot: Bot, receiver: Receiver, chat_id: ChatId) -> Result<()> {
+async fn notificator_loop(
+    bot: Bot,
+    receiver: Receiver,
+    mut notifier: TelegramNotifier,
+    requests: Shared<Requests>,
+    cameras: Vec<String>,
+    started: Instant,
+    shutdown: ShutdownToken) -> Result<()>
+{
+    let mut backoff = Backoff::new(RETRY_INITIAL, RETRY_MAX);
+    let mut stopping = false;
+
     loop {
         sleep(Duration::from_secs(1)).await;
-        match receiver.try_recv() {
-            Ok(Signal::MotionCaptured(path)) => {
-                info!("Captured motion at {:?}", path);
-                bot.send_message(chat_id, "Detected motion".to_string()).await?;
-                let path_buf = PathBuf::from_str(&path)?;
-                bot.send_video(chat_id, InputFile::file(path_buf)).await?;
+
+        while let Ok(signal) = receiver.try_recv() {
+            match &signal {
+                Signal::StatusReport(status) => {
+                    for request in requests.lock().unwrap().status.iter_mut() {
+                        if !request.reports.iter().any(|report| report.camera == status.camera) {
+                            request.reports.push(status.clone());
+                        }
+                    }
+                }
+                Signal::Snapshot(snapshot) => {
+                    for chat_id in snapshot_requesters(&requests, &snapshot.camera, &cameras) {
+                        let sent = bot.send_photo(
+                            chat_id,
+                            InputFile::memory(snapshot.jpeg.clone()).file_name(format!("{}.jpg", snapshot.camera))
+                        ).caption(snapshot.camera.clone()).await;
+                        if let Err(e) = sent {
+                            warn!("Cannot send snapshot to {}: {}", chat_id, e);
+                        }
+                    }
+                }
+                Signal::SnapshotUnavailable(camera) => {
+                    for chat_id in snapshot_requesters(&requests, camera, &cameras) {
+                        let text = format!("{}: no frame available yet", camera);
+                        if let Err(e) = bot.send_message(chat_id, text).await {
+                            warn!("Cannot answer snapshot request of {}: {}", chat_id, e);
+                        }
+                    }
+                }
+                Signal::MotionCaptured(capture) => {
+                    info!("Captured motion on {} at {:?}", capture.camera, capture.path);
+                }
+                Signal::CamerasStopped => {
+                    stopping = true;
+                }
+                _ => {}
+            };
+
+            if let Some(alert) = Alert::from_signal(&signal) {
+                notifier.notify(&alert, Local::now())?;
             }
-            Ok(_) | Err(_) => {}
+        }
+
+        // A request is answered once every camera has reported, or when it times out;
+        // cameras that haven't reported by then are listed as not responding
+        let answered: Vec<StatusRequest> = {
+            let mut requests = requests.lock().unwrap();
+            let (answered, waiting) = std::mem::take(&mut requests.status).into_iter()
+                .partition(|request| {
+                    request.since.elapsed() >= STATUS_TIMEOUT
+                        || cameras.iter().all(|name| request.reports.iter().any(|report| &report.camera == name))
+                });
+            requests.status = waiting;
+            answered
         };
+        for request in answered {
+            let text = format_status(&request.reports, &cameras, started.elapsed());
+            if let Err(e) = bot.send_message(request.chat_id, text).await {
+                warn!("Cannot send status to {}: {}", request.chat_id, e);
+            }
+        }
+
+        deliver(&bot, &mut notifier.outbox, &mut backoff).await?;
+
+        if stopping {
+            break
+        }
+    }
+
+    // Give the last alerts a chance; whatever is left stays in the outbox for the next run
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    while !notifier.outbox.is_empty() && Instant::now() < deadline {
+        sleep(Duration::from_secs(1)).await;
+        deliver(&bot, &mut notifier.outbox, &mut backoff).await?;
+    }
+    if !notifier.outbox.is_empty() {
+        warn!("{} notifications left undelivered", notifier.outbox.len());
+    }
+
+    info!("Stopping telegram bot");
+    loop {
+        match shutdown.shutdown() {
+            Ok(stopped) => break stopped.await,
+            // The dispatcher hasn't started yet
+            Err(_) => sleep(Duration::from_millis(100)).await,
+        }
     }
+    Ok(())
 }
 
 
-async fn handle_commands(bot: Bot, msg: Message, sender: Sender, chat_id: ChatId) -> Result<()> {
+/// Sends notifications from the outbox in order, stopping at the first failure that may pass
+///
+/// Only notifications Telegram can never accept are dropped. Errors about the token or the chat
+/// (kicked bot, chat not found after a migration, ...) may be fixed without restarting, so
+/// those notifications are kept and retried.
+async fn deliver(bot: &Bot, outbox: &mut Outbox<Notification>, backoff: &mut Backoff) -> Result<()> {
+    while let Some(notification) = outbox.front() {
+        if !backoff.ready() {
+            break
+        }
 
-    // Make sure that only our chat is supported
-    if chat_id != msg.chat.id {
-        info!("Unauthorized attempt from {:?}", msg.chat);
-        return Ok(());
+        match send_notification(bot, notification).await {
+            Ok(()) => {
+                backoff.reset();
+                outbox.pop()?;
+            }
+            Err(RequestError::Api(e)) if is_unsendable(&e) => {
+                error!("Dropping notification {:?}: {}", notification, e);
+                outbox.pop()?;
+            }
+            Err(RequestError::Api(e)) => {
+                let delay = backoff.fail();
+                error!("Telegram refused notification {:?} ({} pending), retrying in {:?}: {}",
+                    notification, outbox.len(), delay, e);
+                break
+            }
+            Err(e) => {
+                let delay = backoff.fail();
+                warn!("Cannot deliver notification ({} pending), retrying in {:?}: {}", outbox.len(), delay, e);
+                break
+            }
+        }
     }
+    Ok(())
+}
+
+
+/// Whether Telegram refused the notification for its content, so it can never be sent
+fn is_unsendable(error: &ApiError) -> bool {
+    matches!(error, ApiError::MessageTextIsEmpty | ApiError::MessageIsTooLong | ApiError::WrongFileId)
+}
+
+
+async fn send_notification(bot: &Bot, notification: &Notification) -> Result<(), RequestError> {
+    match notification {
+        Notification::Text { chat_id, text } => {
+            bot.send_message(ChatId(*chat_id), text.clone()).await?;
+        }
+        Notification::Video { chat_id, path } => {
+            // The clip may have been removed by the retention policy while we were offline
+            if !Path::new(path).exists() {
+                warn!("Clip {} no longer exists, not sending it", path);
+                return Ok(())
+            }
+            let path_buf = PathBuf::from_str(path).unwrap();
+            bot.send_video(ChatId(*chat_id), InputFile::file(path_buf)).await?;
+        }
+    }
+    Ok(())
+}
+
+
+/// Chats that asked recently enough for a snapshot of the camera and haven't got it yet
+///
+/// Requests are done once every camera they asked for has answered.
+fn snapshot_requesters(requests: &Shared<Requests>, camera: &str, cameras: &[String]) -> Vec<ChatId> {
+    let mut requests = requests.lock().unwrap();
+    requests.snapshot.retain(|request| request.since.elapsed() < SNAPSHOT_TIMEOUT);
+
+    let mut chats = Vec::new();
+    for request in requests.snapshot.iter_mut() {
+        let wanted = request.camera.as_deref().map_or(true, |name| name == camera);
+        if wanted && !request.answered.iter().any(|name| name == camera) {
+            request.answered.push(camera.to_string());
+            chats.push(request.chat_id);
+        }
+    }
+    requests.snapshot.retain(|request| match request.camera {
+        Some(_) => request.answered.is_empty(),
+        None => cameras.iter().any(|name| !request.answered.contains(name)),
+    });
+    chats
+}
+
+
+async fn handle_commands(
+    msg: Message,
+    bot: Bot,
+    sender: Sender,
+    access: Arc<Access>,
+    requests: Shared<Requests>,
+    cameras: Arc<Vec<String>>) -> Result<()>
+{
+    // Make sure that only our chats are supported
+    let role = match access.role(&msg) {
+        Some(role) => role,
+        None => {
+            info!("Unauthorized attempt from {:?}", msg.chat);
+            return Ok(());
+        }
+    };
 
     let msg_text = msg.text();
 
@@ -94,13 +433,194 @@ async fn handle_commands(bot: Bot, msg: Message, sender: Sender, chat_id: ChatId
 
     info!("{}", msg.text().unwrap());
 
-    let command = Command::parse(msg.text().unwrap(), "Bot");
+    let command = match Command::parse(msg.text().unwrap(), "Bot") {
+        Ok(command) => command,
+        Err(_) => return Ok(()),
+    };
+
+    if role < command.required_role() {
+        info!("{:?} is not allowed to {:?} in {:?}", msg.from(), command, msg.chat.id);
+        return Ok(());
+    }
 
     match command {
-        Ok(Command::StopCamera) => { sender.try_send(Signal::StopCamera)? },
-        Ok(Command::StartCamera) => { sender.try_send(Signal::StartCamera)? }
-        Err(_) => {}
+        Command::StopCamera(camera) => { sender.try_send(Signal::StopCamera(camera_name(camera)))? },
+        Command::StartCamera(camera) => { sender.try_send(Signal::StartCamera(camera_name(camera)))? }
+        Command::Status => {
+            requests.lock().unwrap().status.push(StatusRequest {
+                chat_id: msg.chat.id,
+                since: Instant::now(),
+                reports: Vec::new(),
+            });
+            sender.try_send(Signal::StatusRequest)?
+        }
+        Command::Snapshot(camera) => {
+            let camera = camera_name(camera);
+            if let Some(name) = &camera {
+                if !cameras.contains(name) {
+                    bot.send_message(msg.chat.id, format!("Unknown camera {}", name)).await?;
+                    return Ok(())
+                }
+            }
+            requests.lock().unwrap().snapshot.push(SnapshotRequest {
+                chat_id: msg.chat.id,
+                camera: camera.clone(),
+                since: Instant::now(),
+                answered: Vec::new(),
+            });
+            sender.try_send(Signal::SnapshotRequest(camera))?
+        }
+        Command::Arm(camera) => { sender.try_send(Signal::Arm(camera_name(camera)))? }
+        Command::Disarm(camera) => { sender.try_send(Signal::Disarm(camera_name(camera)))? }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+
+fn camera_name(arg: String) -> Option<String> {
+    let name = arg.trim();
+    if name.is_empty() { None } else { Some(name.to_string()) }
+}
+
+
+/// One entry per configured camera, in config order
+fn format_status(reports: &[CameraStatus], cameras: &[String], uptime: Duration) -> String {
+    let mut text = String::new();
+    for camera in cameras {
+        let report = match reports.iter().find(|report| &report.camera == camera) {
+            Some(report) => report,
+            None => {
+                text.push_str(&format!("{}: not responding\n", camera));
+                continue
+            }
+        };
+        let last_motion = match report.last_motion {
+            Some(time) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => "never".to_string(),
+        };
+        text.push_str(&format!(
+            "{}: {}, {}, {}\n  frames processed: {}\n  last motion: {}\n  disk usage: {:.1} MB\n",
+            report.camera,
+            if report.running { "running" } else { "stopped" },
+            if report.armed { "armed" } else { "disarmed" },
+            report.state,
+            report.frames_processed,
+            last_motion,
+            report.disk_usage as f64 / (1024. * 1024.),
+        ));
+    }
+    let secs = uptime.as_secs();
+    text.push_str(&format!(
+        "uptime: {}d {:02}:{:02}:{:02}",
+        secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60
+    ));
+    text
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use teloxide::Bot;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use crate::outbox::{Backoff, Outbox};
+    use super::{deliver, format_status, Notification};
+    use crate::signals::CameraStatus;
+
+
+    const MESSAGE: &str = r#"{"ok":true,"result":{"message_id":1,"date":0,"chat":{"id":1,"type":"private","first_name":"a"},"from":{"id":1,"is_bot":true,"first_name":"bot"},"text":"x"}}"#;
+
+
+    /// Serves sendMessage, dropping the first `failures` connections without an answer
+    async fn fake_api(mut failures: usize, bodies: Arc<Mutex<Vec<String>>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                if failures > 0 {
+                    failures -= 1;
+                    continue
+                }
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length = text[..end].lines()
+                            .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                            .and_then(|v| v.parse::<usize>().ok())
+                            .unwrap_or(0);
+                        if request.len() >= end + 4 + length {
+                            bodies.lock().unwrap().push(text[end + 4..].to_string());
+                            break
+                        }
+                    }
+                    if n == 0 { break }
+                }
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    MESSAGE.len(), MESSAGE
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        url
+    }
+
+
+    #[test]
+    fn status_lists_silent_cameras() {
+        let reports = vec![CameraStatus {
+            camera: "yard".to_string(),
+            running: true,
+            armed: true,
+            state: "watching".to_string(),
+            frames_processed: 10,
+            last_motion: None,
+            disk_usage: 0,
+        }];
+        let cameras = vec!["door".to_string(), "yard".to_string()];
+
+        let text = format_status(&reports, &cameras, Duration::from_secs(61));
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "door: not responding");
+        assert_eq!(lines[1], "yard: running, armed, watching");
+        assert_eq!(lines.last(), Some(&"uptime: 0d 00:01:01"));
+    }
+
+
+    #[tokio::test]
+    async fn outbox_delivered_in_order_after_failure() {
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let url = fake_api(1, bodies.clone()).await;
+        let bot = Bot::new("token").set_api_url(url::Url::parse(&url).unwrap());
+
+        let path = std::env::temp_dir().join(format!("telegram-outbox-test-{}.jsonl", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let mut outbox = Outbox::open(path.to_str().unwrap()).unwrap();
+        outbox.push(Notification::Text { chat_id: 1, text: "first".to_string() }).unwrap();
+        outbox.push(Notification::Text { chat_id: 1, text: "second".to_string() }).unwrap();
+
+        let mut backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(50));
+        for _ in 0..50 {
+            deliver(&bot, &mut outbox, &mut backoff).await.unwrap();
+            if outbox.is_empty() { break }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        assert!(outbox.is_empty());
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert!(bodies[0].contains("first"));
+        assert!(bodies[1].contains("second"));
+        std::fs::remove_file(&path).ok();
+    }
+}